@@ -29,6 +29,7 @@ fn open_db() -> (OptimisticTransactionDB, TempDir) {
     )
 }
 
+#[allow(clippy::clone_on_copy)]
 fn benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("cf_smt_update");
     for count in [100, 500, 1000] {
@@ -9,6 +9,7 @@ use rocksdb::{prelude::Open, DBVector, OptimisticTransactionDB};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use smt_rocksdb_store::default_store::DefaultStore;
+use smt_rocksdb_store::root::{commit_root, open_tree};
 use sparse_merkle_tree::blake2b::Blake2bHasher;
 use sparse_merkle_tree::traits::Value;
 use sparse_merkle_tree::{SparseMerkleTree, H256};
@@ -74,13 +75,15 @@ impl RpcServerImpl {
 
 #[async_trait]
 impl RpcServer for RpcServerImpl {
+    #[allow(clippy::clone_on_copy)]
     async fn update_all(&self, kvs: Vec<(SmtKey, SmtValue)>) -> Result<SmtRoot, Error> {
         let kvs: Vec<(H256, SmtValue)> = kvs.into_iter().map(|(k, v)| (k.0.into(), v)).collect();
 
         let tx = self.db.transaction_default();
-        let mut rocksdb_store_smt =
-            DefaultStoreSMT::new_with_store(DefaultStore::new(&tx)).unwrap();
+        let mut rocksdb_store_smt: DefaultStoreSMT<_, ()> =
+            open_tree(DefaultStore::new(&tx)).unwrap();
         rocksdb_store_smt.update_all(kvs).expect("update_all error");
+        commit_root(&rocksdb_store_smt).expect("commit_root error");
         tx.commit().expect("db commit error");
         Ok(SmtRoot(rocksdb_store_smt.root().clone().into()))
     }
@@ -88,8 +91,8 @@ impl RpcServer for RpcServerImpl {
     async fn merkle_proof(&self, keys: Vec<SmtKey>) -> Result<SmtProof, Error> {
         let keys: Vec<H256> = keys.into_iter().map(|k| k.0.into()).collect();
         let snapshot = self.db.snapshot();
-        let rocksdb_store_smt =
-            DefaultStoreSMT::new_with_store(DefaultStore::<_, ()>::new(&snapshot)).unwrap();
+        let rocksdb_store_smt: DefaultStoreSMT<_, ()> =
+            open_tree(DefaultStore::new(&snapshot)).unwrap();
         let proof = rocksdb_store_smt
             .merkle_proof(keys.clone())
             .expect("merkle_proof error");
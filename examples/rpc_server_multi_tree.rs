@@ -5,14 +5,13 @@ use jsonrpsee::core::{async_trait, Error};
 use jsonrpsee::http_server::HttpServerBuilder;
 use jsonrpsee::proc_macros::rpc;
 
-use rocksdb::{
-    prelude::{Iterate, Open},
-    DBVector, OptimisticTransactionDB,
-};
-use rocksdb::{Direction, IteratorMode};
+use rocksdb::{prelude::Open, DBVector, OptimisticTransactionDB};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use smt_rocksdb_store::default_store::DefaultStoreMultiTree;
+use smt_rocksdb_store::drop::drop_tree;
+use smt_rocksdb_store::namespace::Namespace;
+use smt_rocksdb_store::root::{commit_root, open_tree};
 use sparse_merkle_tree::blake2b::Blake2bHasher;
 use sparse_merkle_tree::traits::Value;
 use sparse_merkle_tree::{SparseMerkleTree, H256};
@@ -58,6 +57,11 @@ impl AsRef<[u8]> for SmtValue {
 type DefaultStoreMultiSMT<'a, T, W> =
     SparseMerkleTree<Blake2bHasher, SmtValue, DefaultStoreMultiTree<'a, T, W>>;
 
+// Store each tree under its namespace, so trees like "tree" and "tree1" can not overwrite each other.
+fn namespace(tree: &str) -> Result<Namespace, Error> {
+    Namespace::new(tree.as_bytes()).map_err(|e| Error::Custom(e.to_string()))
+}
+
 #[rpc(server)]
 pub trait Rpc {
     #[method(name = "update_all")]
@@ -82,25 +86,26 @@ impl RpcServerImpl {
 
 #[async_trait]
 impl RpcServer for RpcServerImpl {
+    #[allow(clippy::clone_on_copy)]
     async fn update_all(&self, tree: &str, kvs: Vec<(SmtKey, SmtValue)>) -> Result<SmtRoot, Error> {
         let kvs: Vec<(H256, SmtValue)> = kvs.into_iter().map(|(k, v)| (k.0.into(), v)).collect();
+        let namespace = namespace(tree)?;
 
         let tx = self.db.transaction_default();
-        let mut rocksdb_store_smt =
-            DefaultStoreMultiSMT::new_with_store(DefaultStoreMultiTree::new(tree.as_bytes(), &tx))
-                .unwrap();
+        let mut rocksdb_store_smt: DefaultStoreMultiSMT<_, ()> =
+            open_tree(DefaultStoreMultiTree::with_namespace(&namespace, &tx)).unwrap();
         rocksdb_store_smt.update_all(kvs).expect("update_all error");
+        commit_root(&rocksdb_store_smt).expect("commit_root error");
         tx.commit().expect("db commit error");
         Ok(SmtRoot(rocksdb_store_smt.root().clone().into()))
     }
 
     async fn merkle_proof(&self, tree: &str, keys: Vec<SmtKey>) -> Result<SmtProof, Error> {
         let keys: Vec<H256> = keys.into_iter().map(|k| k.0.into()).collect();
+        let namespace = namespace(tree)?;
         let snapshot = self.db.snapshot();
-        let rocksdb_store_smt = DefaultStoreMultiSMT::new_with_store(
-            DefaultStoreMultiTree::<_, ()>::new(tree.as_bytes(), &snapshot),
-        )
-        .unwrap();
+        let rocksdb_store_smt: DefaultStoreMultiSMT<_, ()> =
+            open_tree(DefaultStoreMultiTree::with_namespace(&namespace, &snapshot)).unwrap();
         let proof = rocksdb_store_smt
             .merkle_proof(keys.clone())
             .expect("merkle_proof error");
@@ -108,31 +113,13 @@ impl RpcServer for RpcServerImpl {
     }
 
     async fn clear(&self, tree: &str) -> Result<(), Error> {
-        // OptimisticTransactionDB does not support delete_range, so we have to iterate all keys and update them to zero as a workaround
-        let snapshot = self.db.snapshot();
-        let prefix = tree.as_bytes();
-        let prefix_len = prefix.len();
-        let leaf_key_len = prefix_len + 32;
-        let kvs: Vec<(H256, SmtValue)> = snapshot
-            .iterator(IteratorMode::From(prefix, Direction::Forward))
-            .take_while(|(k, _)| k.starts_with(prefix))
-            .filter_map(|(k, _)| {
-                if k.len() != leaf_key_len {
-                    None
-                } else {
-                    let leaf_key: [u8; 32] = k[prefix_len..].try_into().expect("checked 32 bytes");
-                    Some((leaf_key.into(), SmtValue::zero()))
-                }
-            })
-            .collect();
-
+        // OptimisticTransaction does not support delete_range, so drop_tree deletes the keys of the tree one by one
+        let namespace = namespace(tree)?;
         let tx = self.db.transaction_default();
-        let mut rocksdb_store_smt =
-            DefaultStoreMultiSMT::new_with_store(DefaultStoreMultiTree::new(tree.as_bytes(), &tx))
-                .unwrap();
-        rocksdb_store_smt.update_all(kvs).expect("update_all error");
+        let store: DefaultStoreMultiTree<_, ()> =
+            DefaultStoreMultiTree::with_namespace(&namespace, &tx);
+        drop_tree(&store).expect("drop_tree error");
         tx.commit().expect("db commit error");
-        assert_eq!(rocksdb_store_smt.root(), &H256::zero());
         Ok(())
     }
 }
@@ -0,0 +1,202 @@
+use rocksdb::{
+    prelude::{DeleteCF, GetCF, IterateCF, PutCF},
+    ColumnFamily, DBIterator, DBVector, Direction, IteratorMode, ReadOptions, WriteBatch,
+};
+use sparse_merkle_tree::error::Error;
+
+use crate::multi_get::MultiGetCF;
+
+/// A raw key-value pair read from the database.
+pub type KeyValue = (Box<[u8]>, Box<[u8]>);
+
+/// A column family and a key prefix, which together hold one kind of records of a store.
+#[derive(Clone, Copy)]
+pub struct KeySpace<'a> {
+    /// The column family of the records, `None` for the default column family.
+    pub col: Option<&'a ColumnFamily>,
+    /// The prefix of every key in this space.
+    pub prefix: &'a [u8],
+}
+
+impl<'a> KeySpace<'a> {
+    pub fn new(col: Option<&'a ColumnFamily>, prefix: &'a [u8]) -> Self {
+        KeySpace { col, prefix }
+    }
+
+    /// Returns the database key of `key` in this space.
+    pub fn key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix, key].concat()
+    }
+
+    pub fn get<T>(
+        &self,
+        db: &T,
+        key: &[u8],
+        read_options: Option<&ReadOptions>,
+    ) -> Result<Option<DBVector>, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        db.get_cf_full(self.col, self.key(key), read_options)
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    /// Read several keys of this space with one `multi_get`, returns the values in the order of `keys`.
+    pub fn get_many<T, K>(
+        &self,
+        db: &T,
+        keys: &[K],
+        read_options: Option<&ReadOptions>,
+    ) -> Result<Vec<Option<DBVector>>, Error>
+    where
+        T: MultiGetCF,
+        K: AsRef<[u8]>,
+    {
+        let keys: Vec<Vec<u8>> = keys.iter().map(|key| self.key(key.as_ref())).collect();
+        db.multi_get_cf_full(self.col, &keys, read_options)
+    }
+
+    pub fn put<T, W>(
+        &self,
+        db: &T,
+        key: &[u8],
+        value: &[u8],
+        write_options: Option<&W>,
+    ) -> Result<(), Error>
+    where
+        T: PutCF<W>,
+    {
+        db.put_cf_full(self.col, self.key(key), value, write_options)
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    pub fn delete<T, W>(&self, db: &T, key: &[u8], write_options: Option<&W>) -> Result<(), Error>
+    where
+        T: DeleteCF<W>,
+    {
+        db.delete_cf_full(self.col, self.key(key), write_options)
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    /// Add a put of `key` in this space to `batch`.
+    pub fn batch_put(&self, batch: &mut WriteBatch, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        match self.col {
+            Some(col) => batch.put_cf(col, self.key(key), value),
+            None => batch.put(self.key(key), value),
+        }
+        .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    /// Add a delete of `key` in this space to `batch`.
+    pub fn batch_delete(&self, batch: &mut WriteBatch, key: &[u8]) -> Result<(), Error> {
+        match self.col {
+            Some(col) => batch.delete_cf(col, self.key(key)),
+            None => batch.delete(self.key(key)),
+        }
+        .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    /// Iterate over all records in this space, including the metadata records.
+    pub fn scan<'b, T>(
+        &self,
+        db: &'b T,
+        read_options: Option<&ReadOptions>,
+    ) -> Result<impl Iterator<Item = KeyValue> + 'b, Error>
+    where
+        T: IterateCF,
+        'a: 'b,
+    {
+        self.scan_from(db, None, Direction::Forward, read_options)
+    }
+
+    /// Iterate over all records in this space in the given direction, starting from the first key
+    /// at or after `from` (before `from` in reverse), or from the first (last in reverse) key of the space.
+    pub fn scan_from<'b, T>(
+        &self,
+        db: &'b T,
+        from: Option<&[u8]>,
+        direction: Direction,
+        read_options: Option<&ReadOptions>,
+    ) -> Result<impl Iterator<Item = KeyValue> + 'b, Error>
+    where
+        T: IterateCF,
+        'a: 'b,
+    {
+        let reverse = matches!(direction, Direction::Reverse);
+        let start = match from {
+            Some(from) => Some(self.key(from)),
+            None if reverse => prefix_end(self.prefix),
+            None => Some(self.prefix.to_vec()),
+        };
+        let mode = match &start {
+            Some(start) => IteratorMode::From(start, direction),
+            None => IteratorMode::End,
+        };
+        let default_read_options = ReadOptions::default();
+        let read_options = read_options.unwrap_or(&default_read_options);
+        let iter: DBIterator<'b> = match self.col {
+            Some(col) => db
+                .iterator_cf_opt(col, mode, read_options)
+                .map_err(|e| Error::Store(e.to_string()))?,
+            None => db.iterator_opt(mode, read_options),
+        };
+        let prefix = self.prefix;
+        Ok(iter
+            // in reverse, the iteration can start at a key after the space, i.e. the end of the prefix
+            .skip_while(move |(k, _)| reverse && !k.starts_with(prefix) && k.as_ref() > prefix)
+            .take_while(move |(k, _)| k.starts_with(prefix)))
+    }
+
+    /// Iterate over the records in this space whose key, without the prefix, has the given length.
+    pub fn iter<'b, T>(
+        &self,
+        db: &'b T,
+        key_len: usize,
+        read_options: Option<&ReadOptions>,
+    ) -> Result<impl Iterator<Item = KeyValue> + 'b, Error>
+    where
+        T: IterateCF,
+        'a: 'b,
+    {
+        let key_len = self.prefix.len() + key_len;
+        Ok(self
+            .scan(db, read_options)?
+            .filter(move |(k, _)| k.len() == key_len))
+    }
+}
+
+/// Returns the smallest key greater than every key starting with `prefix`, `None` if there is no such key.
+pub(crate) fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let len = prefix.iter().rposition(|&b| b != u8::MAX)?;
+    let mut end = prefix[..=len].to_vec();
+    end[len] += 1;
+    Some(end)
+}
+
+/// Access to the database and the key spaces of a SMT store, used by the helpers that work with every store type.
+pub trait Backend {
+    /// The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` / `Snapshot` etc.
+    type DB;
+    /// The write options of the database, can be a `WriteOptions` / `()` etc.
+    type WriteOptions;
+
+    fn db(&self) -> &Self::DB;
+
+    /// The read options of the store, `None` for the default options.
+    fn read_options(&self) -> Option<&ReadOptions>;
+
+    /// The write options of the store, `None` for the default options.
+    fn write_options(&self) -> Option<&Self::WriteOptions>;
+
+    /// The key space of the branches, which also holds the metadata records of the store.
+    fn branch_space(&self) -> KeySpace<'_>;
+
+    /// The key space of the leaves.
+    fn leaf_space(&self) -> KeySpace<'_>;
+
+    /// Returns true if the store writes the format record along with its next root branch, which only the stores
+    /// created by `open` do when the record is missing, see `format::FORMAT_KEY`.
+    fn records_format(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::{HashMap, HashSet};
+
+use rocksdb::DBVector;
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, Value},
+    BranchKey, BranchNode, MerkleProof, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::Backend,
+    multi_get::MultiGetCF,
+    serde::{branch_key_to_vec, slice_to_branch_node},
+};
+
+/// Batched reads of a SMT store.
+pub trait StoreBatchReadOps<V>: StoreReadOps<V> {
+    /// Read several branches, returns the branches in the order of `branch_keys`.
+    fn get_branches(&self, branch_keys: &[BranchKey]) -> Result<Vec<Option<BranchNode>>, Error>;
+
+    /// Read several leaves, returns the leaves in the order of `leaf_keys`.
+    fn get_leaves(&self, leaf_keys: &[H256]) -> Result<Vec<Option<V>>, Error>;
+}
+
+impl<V, S> StoreBatchReadOps<V> for S
+where
+    V: From<DBVector>,
+    S: Backend + StoreReadOps<V>,
+    S::DB: MultiGetCF,
+{
+    fn get_branches(&self, branch_keys: &[BranchKey]) -> Result<Vec<Option<BranchNode>>, Error> {
+        let keys: Vec<Vec<u8>> = branch_keys.iter().map(branch_key_to_vec).collect();
+        self.branch_space()
+            .get_many(self.db(), &keys, self.read_options())?
+            .into_iter()
+            .map(|v| {
+                v.map(|v| slice_to_branch_node(&v).map_err(|e| Error::Store(e.to_string())))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    fn get_leaves(&self, leaf_keys: &[H256]) -> Result<Vec<Option<V>>, Error> {
+        let keys: Vec<&[u8]> = leaf_keys.iter().map(H256::as_slice).collect();
+        let values = self
+            .leaf_space()
+            .get_many(self.db(), &keys, self.read_options())?;
+        Ok(values.into_iter().map(|v| v.map(Into::into)).collect())
+    }
+}
+
+// A store serving the branches read ahead of a proof generation, the other branches on the paths of the keys
+// are below an empty subtree. The leaves are read from the wrapped store.
+struct PrefetchedStore<'a, S> {
+    inner: &'a S,
+    branches: HashMap<BranchKey, Option<BranchNode>>,
+}
+
+impl<'a, V, S> StoreReadOps<V> for PrefetchedStore<'a, S>
+where
+    S: StoreReadOps<V>,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        Ok(self.branches.get(branch_key).cloned().flatten())
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.inner.get_leaf(leaf_key)
+    }
+}
+
+/// Generate the merkle proof of `keys` like `SparseMerkleTree::merkle_proof`, reading the branches on the paths
+/// of the keys ahead with one batched read per level of the tree.
+///
+/// The path of a key is read down to the first missing branch or empty subtree, the branches below are known to be
+/// missing without reading them.
+pub fn merkle_proof<H, V, S>(
+    tree: &SparseMerkleTree<H, V, S>,
+    keys: Vec<H256>,
+) -> Result<MerkleProof, Error>
+where
+    H: Hasher + Default,
+    V: Value,
+    S: StoreBatchReadOps<V>,
+{
+    let mut branches = HashMap::new();
+    // the keys whose subtree at the current height is not known to be empty
+    let mut paths = keys.clone();
+    for height in (0..=u8::MAX).rev() {
+        let level: HashSet<BranchKey> = paths
+            .iter()
+            .map(|key| BranchKey::new(height, key.parent_path(height)))
+            .collect();
+        let level: Vec<BranchKey> = level.into_iter().collect();
+        let nodes = tree.store().get_branches(&level)?;
+        branches.extend(level.into_iter().zip(nodes));
+        paths.retain(
+            |key| match &branches[&BranchKey::new(height, key.parent_path(height))] {
+                Some(node) if key.is_right(height) => !node.right.is_zero(),
+                Some(node) => !node.left.is_zero(),
+                None => false,
+            },
+        );
+        if paths.is_empty() {
+            break;
+        }
+    }
+    let store = PrefetchedStore {
+        inner: tree.store(),
+        branches,
+    };
+    SparseMerkleTree::<H, V, _>::new(*tree.root(), store).merkle_proof(keys)
+}
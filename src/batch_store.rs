@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use rocksdb::{
+    prelude::{GetCF, WriteOps},
+    ReadOptions, WriteBatch, WriteOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::Backend,
+    format::{is_root_branch_key, StoreFormat, FORMAT_KEY},
+    root::{open_tree_with, ROOT_KEY},
+    serde::{branch_key_to_vec, branch_node_to_vec},
+};
+
+/// A SMT `Store` which buffers the writes of another store into a RocksDB `WriteBatch`,
+/// so a set of updates and the new root are applied to the database atomically by `commit`.
+///
+/// Reads see the pending writes first, then fall back to the wrapped store.
+/// Pending writes are discarded if the store is dropped without `commit`.
+pub struct WriteBatchStore<S, V> {
+    inner: S,
+    batch: WriteBatch,
+    // The pending branches and leaves of the batch, `None` for a pending delete.
+    branches: HashMap<BranchKey, Option<BranchNode>>,
+    leaves: HashMap<H256, Option<V>>,
+    // Whether the wrapped store has no format record, which is then written along with the root branches until
+    // a commit, see `Backend::records_format`.
+    record_format: bool,
+}
+
+impl<S: Backend, V> WriteBatchStore<S, V> {
+    pub fn new(inner: S) -> Self {
+        WriteBatchStore {
+            record_format: inner.records_format(),
+            inner,
+            batch: WriteBatch::default(),
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+        }
+    }
+}
+
+impl<S, V> WriteBatchStore<S, V> {
+    /// The wrapped store, which does not see the pending writes.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the number of pending writes in the batch.
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    // The wrapped store and the batch of the pending writes, to add records which are written along with them.
+    pub(crate) fn inner_and_batch(&mut self) -> (&S, &mut WriteBatch) {
+        (&self.inner, &mut self.batch)
+    }
+
+    /// Drop all pending writes.
+    pub fn discard(&mut self) {
+        self.batch = WriteBatch::default();
+        self.branches.clear();
+        self.leaves.clear();
+    }
+
+    /// Atomically apply the pending writes and record `root` as the committed root, see `root::ROOT_KEY`.
+    ///
+    /// The batch is written with the write options of the wrapped store. On success the store is empty
+    /// and can buffer the next set of updates, on failure the pending writes are kept.
+    pub fn commit(&mut self, root: &H256) -> Result<(), Error>
+    where
+        S: Backend<WriteOptions = WriteOptions>,
+        S::DB: WriteOps,
+    {
+        self.inner
+            .branch_space()
+            .batch_put(&mut self.batch, ROOT_KEY, root.as_slice())?;
+        self.inner
+            .db()
+            .write_full(&self.batch, self.inner.write_options())
+            .map_err(|e| Error::Store(e.to_string()))?;
+        self.record_format = false;
+        self.discard();
+        Ok(())
+    }
+}
+
+impl<S, V> StoreReadOps<V> for WriteBatchStore<S, V>
+where
+    S: StoreReadOps<V>,
+    V: Clone,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        match self.branches.get(branch_key) {
+            Some(branch) => Ok(branch.clone()),
+            None => self.inner.get_branch(branch_key),
+        }
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        match self.leaves.get(leaf_key) {
+            Some(leaf) => Ok(leaf.clone()),
+            None => self.inner.get_leaf(leaf_key),
+        }
+    }
+}
+
+impl<S, V> StoreWriteOps<V> for WriteBatchStore<S, V>
+where
+    S: Backend,
+    V: Value + AsRef<[u8]>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let space = self.inner.branch_space();
+        if self.record_format && is_root_branch_key(&node_key) {
+            space.batch_put(
+                &mut self.batch,
+                FORMAT_KEY,
+                &StoreFormat::current().to_vec(),
+            )?;
+        }
+        space.batch_put(
+            &mut self.batch,
+            &branch_key_to_vec(&node_key),
+            &branch_node_to_vec(&branch),
+        )?;
+        self.branches.insert(node_key, Some(branch));
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.inner
+            .leaf_space()
+            .batch_put(&mut self.batch, leaf_key.as_slice(), leaf.as_ref())?;
+        self.leaves.insert(leaf_key, Some(leaf));
+        Ok(())
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        self.inner
+            .branch_space()
+            .batch_delete(&mut self.batch, &branch_key_to_vec(node_key))?;
+        self.branches.insert(node_key.clone(), None);
+        Ok(())
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.inner
+            .leaf_space()
+            .batch_delete(&mut self.batch, leaf_key.as_slice())?;
+        self.leaves.insert(*leaf_key, None);
+        Ok(())
+    }
+}
+
+/// Open a tree at the last committed root of `store`, buffering its updates in a `WriteBatchStore`.
+pub fn open_tree<H, V, S>(store: S) -> Result<SparseMerkleTree<H, V, WriteBatchStore<S, V>>, Error>
+where
+    H: Hasher + Default,
+    V: Value + Clone,
+    S: Backend + StoreReadOps<V>,
+    S::DB: GetCF<ReadOptions>,
+{
+    open_tree_with(store, WriteBatchStore::new)
+}
+
+/// Atomically apply the pending updates of a tree along with its current root.
+pub fn commit_tree<H, V, S>(
+    tree: &mut SparseMerkleTree<H, V, WriteBatchStore<S, V>>,
+) -> Result<(), Error>
+where
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: WriteOps,
+{
+    let root = *tree.root();
+    tree.store_mut().commit(&root)
+}
@@ -0,0 +1,209 @@
+use std::path::{Path, PathBuf};
+
+use rocksdb::{
+    prelude::{GetCF, IngestExternalFileCF, IterateCF},
+    ColumnFamily, Direction, IngestExternalFileOptions, Options, ReadOptions, SstFileWriter,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, Value},
+    BranchKey, BranchNode, H256,
+};
+
+use crate::{
+    backend::Backend,
+    format::{is_root_branch_key, missing_format_record, FORMAT_KEY},
+    iter::StoreIterOps,
+    repair::BranchBuilder,
+    root::ROOT_KEY,
+    serde::{branch_key_to_vec, branch_node_to_vec},
+};
+
+// The default number of records sorted in memory and written to each SST file.
+const RECORDS_PER_FILE: usize = 1_000_000;
+
+// Sorts the records of a column family into SST files.
+struct SstFiles<'a> {
+    dir: &'a Path,
+    // The name of the records, which prefixes the file names.
+    name: &'static str,
+    records_per_file: usize,
+    records: Vec<(Vec<u8>, Vec<u8>)>,
+    paths: Vec<PathBuf>,
+}
+
+impl<'a> SstFiles<'a> {
+    fn new(dir: &'a Path, name: &'static str, records_per_file: usize) -> Self {
+        SstFiles {
+            dir,
+            name,
+            records_per_file,
+            records: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), Error> {
+        self.records.push((key, value));
+        if self.records.len() >= self.records_per_file {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // Write the buffered records to a new SST file.
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        self.records.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let path = self
+            .dir
+            .join(format!("{}-{}.sst", self.name, self.paths.len()));
+        let options = Options::default();
+        let mut writer = SstFileWriter::create(&options);
+        writer
+            .open(&path)
+            .map_err(|e| Error::Store(e.to_string()))?;
+        self.paths.push(path);
+        for (key, value) in self.records.drain(..) {
+            writer
+                .put(key, value)
+                .map_err(|e| Error::Store(e.to_string()))?;
+        }
+        writer.finish().map_err(|e| Error::Store(e.to_string()))
+    }
+
+    fn ingest<T: IngestExternalFileCF>(
+        &self,
+        db: &T,
+        col: Option<&ColumnFamily>,
+    ) -> Result<(), Error> {
+        if self.paths.is_empty() {
+            return Ok(());
+        }
+        let mut options = IngestExternalFileOptions::default();
+        options.set_move_files(true);
+        db.ingest_external_file_cf_full(col, self.paths.clone(), Some(&options))
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    // Remove the files which were not ingested.
+    fn remove(&self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Builds the tree of an empty store from a stream of leaves, writing the branches and the leaves to SST files
+/// which are ingested into the column families of the store, instead of updating the tree leaf by leaf.
+pub struct BulkLoader<'a, S> {
+    store: &'a S,
+    // The directory of the SST files.
+    dir: &'a Path,
+    records_per_file: usize,
+}
+
+impl<'a, S> BulkLoader<'a, S>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions> + IterateCF + IngestExternalFileCF,
+{
+    /// Create a loader into `store` which writes the SST files in `dir`, an existing directory preferably on the
+    /// file system of the database, so that the files are moved rather than copied into it.
+    pub fn new(store: &'a S, dir: &'a Path) -> Self {
+        BulkLoader {
+            store,
+            dir,
+            records_per_file: RECORDS_PER_FILE,
+        }
+    }
+
+    /// Sort and write up to `records_per_file` records per SST file, which bounds the memory used by `load`.
+    pub fn with_records_per_file(mut self, records_per_file: usize) -> Self {
+        self.records_per_file = records_per_file.max(1);
+        self
+    }
+
+    /// Load `leaves`, sorted by key in the order of `H256::cmp`, computing the branches bottom-up in one pass,
+    /// and record the root of the tree, returns the root.
+    ///
+    /// The root is the one of an empty tree updated with the same leaves, leaves with a zero value are skipped.
+    /// Returns an error if the store is not empty or if the leaves are not sorted, before anything is ingested.
+    /// The leaves are ingested before the branches and the root, so a failed ingestion may leave the leaves
+    /// in the store without a root, see `repair::rebuild_branches`.
+    pub fn load<H, V, I>(&self, leaves: I) -> Result<H256, Error>
+    where
+        H: Hasher + Default,
+        V: Value + AsRef<[u8]>,
+        I: IntoIterator<Item = (H256, V)>,
+    {
+        if self
+            .store
+            .leaves::<Box<[u8]>>(None, Direction::Forward)?
+            .next()
+            .is_some()
+            || self.store.branches(None)?.next().is_some()
+        {
+            return Err(Error::Store(
+                "the store of a bulk load must be empty".to_string(),
+            ));
+        }
+
+        let mut leaf_files = SstFiles::new(self.dir, "leaves", self.records_per_file);
+        let mut branch_files = SstFiles::new(self.dir, "branches", self.records_per_file);
+        let written = self.write_files::<H, V, I>(leaves, &mut leaf_files, &mut branch_files);
+        let result = written.and_then(|root| {
+            leaf_files.ingest(self.store.db(), self.store.leaf_space().col)?;
+            branch_files.ingest(self.store.db(), self.store.branch_space().col)?;
+            Ok(root)
+        });
+        if result.is_err() {
+            leaf_files.remove();
+            branch_files.remove();
+        }
+        result
+    }
+
+    fn write_files<H, V, I>(
+        &self,
+        leaves: I,
+        leaf_files: &mut SstFiles,
+        branch_files: &mut SstFiles,
+    ) -> Result<H256, Error>
+    where
+        H: Hasher + Default,
+        V: Value + AsRef<[u8]>,
+        I: IntoIterator<Item = (H256, V)>,
+    {
+        let branch_space = self.store.branch_space();
+        let leaf_space = self.store.leaf_space();
+        let record = branch_space.get(self.store.db(), FORMAT_KEY, self.store.read_options())?;
+        let mut format_record = missing_format_record(record.as_deref())?;
+        let mut builder =
+            BranchBuilder::<H, _>::new(|branch_key: BranchKey, branch: BranchNode| {
+                if is_root_branch_key(&branch_key) {
+                    if let Some(record) = format_record.take() {
+                        branch_files.put(branch_space.key(FORMAT_KEY), record)?;
+                    }
+                }
+                branch_files.put(
+                    branch_space.key(&branch_key_to_vec(&branch_key)),
+                    branch_node_to_vec(&branch),
+                )
+            });
+        for (key, value) in leaves {
+            let hash = value.to_h256();
+            builder.push(key, hash)?;
+            if !hash.is_zero() {
+                leaf_files.put(leaf_space.key(key.as_slice()), value.as_ref().to_vec())?;
+            }
+        }
+        let root = builder.finish()?;
+        branch_files.put(branch_space.key(ROOT_KEY), root.as_slice().to_vec())?;
+        leaf_files.flush()?;
+        branch_files.flush()?;
+        Ok(root)
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use rocksdb::ReadOptions;
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{StoreReadOps, StoreWriteOps},
+    BranchKey, BranchNode, H256,
+};
+
+use crate::backend::{Backend, KeySpace};
+
+/// The hit and miss counters of a `BranchCache`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// A branch is cached under the branch key prefix of its store, so multi-tree stores can share a cache.
+type CacheKey = (Vec<u8>, BranchKey);
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<CacheKey, (BranchNode, u64)>,
+    // The keys of the entries by their last use, the first one is the least recently used.
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    // The number of invalidations, a branch read before an invalidation is not cached.
+    generation: u64,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &CacheKey) -> Option<BranchNode> {
+        let (branch, last_use) = self.entries.get_mut(key)?;
+        let key = self.order.remove(last_use).expect("ordered entry");
+        self.tick += 1;
+        *last_use = self.tick;
+        self.order.insert(self.tick, key);
+        Some(branch.clone())
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some((_, last_use)) = self.entries.remove(key) {
+            self.order.remove(&last_use);
+        }
+    }
+}
+
+/// A size-bounded LRU cache of decoded branch nodes, which can be shared by several `CachedStore`s.
+///
+/// Entries are keyed by the branch key and the branch key prefix of the store, so a cache must not be shared
+/// by stores which only differ by their column families, or by stores reading uncommitted data of a transaction.
+pub struct BranchCache {
+    capacity: usize,
+    lru: Mutex<Lru>,
+}
+
+impl BranchCache {
+    /// Create a cache holding at most `capacity` branch nodes.
+    pub fn new(capacity: usize) -> Self {
+        BranchCache {
+            capacity,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of cached branch nodes.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the hit and miss counters since the cache was created or the counters were reset.
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    pub fn reset_stats(&self) {
+        self.lock().stats = CacheStats::default();
+    }
+
+    /// Drop all cached branch nodes.
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.order.clear();
+    }
+
+    // Returns the cached branch, or the generation to insert the branch read from the store with.
+    fn get(&self, key: &CacheKey) -> Result<BranchNode, u64> {
+        let mut lru = self.lock();
+        match lru.touch(key) {
+            Some(branch) => {
+                lru.stats.hits += 1;
+                Ok(branch)
+            }
+            None => {
+                lru.stats.misses += 1;
+                Err(lru.generation)
+            }
+        }
+    }
+
+    // Cache a branch read from the store, unless a branch was invalidated since `generation`,
+    // since the read may have returned the branch before the write which invalidated it.
+    fn insert(&self, key: CacheKey, branch: BranchNode, generation: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lru = self.lock();
+        if lru.generation != generation {
+            return;
+        }
+        lru.remove(&key);
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.order.insert(tick, key.clone());
+        lru.entries.insert(key, (branch, tick));
+        while lru.entries.len() > self.capacity {
+            let (_, key) = lru.order.pop_first().expect("ordered entry");
+            lru.entries.remove(&key);
+        }
+    }
+
+    // Invalidate a branch after it was written to the store.
+    fn invalidate(&self, key: &CacheKey) {
+        let mut lru = self.lock();
+        lru.remove(key);
+        lru.generation += 1;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        // the cache is still consistent if another thread panicked while holding the lock
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A SMT `Store` which caches the decoded branch nodes of another store in a `BranchCache`.
+///
+/// Writes go through to the wrapped store, then invalidate the cached branch. A branch read from the wrapped store
+/// while another branch is invalidated is not cached, since it may predate the write.
+pub struct CachedStore<S> {
+    inner: S,
+    cache: Arc<BranchCache>,
+    // The branch key prefix of the wrapped store.
+    prefix: Vec<u8>,
+}
+
+impl<S: Backend> CachedStore<S> {
+    pub fn new(inner: S, cache: Arc<BranchCache>) -> Self {
+        let prefix = inner.branch_space().prefix.to_vec();
+        CachedStore {
+            inner,
+            cache,
+            prefix,
+        }
+    }
+}
+
+impl<S> CachedStore<S> {
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn cache(&self) -> &Arc<BranchCache> {
+        &self.cache
+    }
+
+    fn cache_key(&self, branch_key: &BranchKey) -> CacheKey {
+        (self.prefix.clone(), branch_key.clone())
+    }
+}
+
+impl<S: Backend> Backend for CachedStore<S> {
+    type DB = S::DB;
+    type WriteOptions = S::WriteOptions;
+
+    fn db(&self) -> &S::DB {
+        self.inner.db()
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.inner.read_options()
+    }
+
+    fn write_options(&self) -> Option<&S::WriteOptions> {
+        self.inner.write_options()
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        self.inner.branch_space()
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        self.inner.leaf_space()
+    }
+
+    fn records_format(&self) -> bool {
+        self.inner.records_format()
+    }
+}
+
+impl<S, V> StoreReadOps<V> for CachedStore<S>
+where
+    S: StoreReadOps<V>,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        let key = self.cache_key(branch_key);
+        let generation = match self.cache.get(&key) {
+            Ok(branch) => return Ok(Some(branch)),
+            Err(generation) => generation,
+        };
+        let branch = self.inner.get_branch(branch_key)?;
+        if let Some(branch) = &branch {
+            self.cache.insert(key, branch.clone(), generation);
+        }
+        Ok(branch)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.inner.get_leaf(leaf_key)
+    }
+}
+
+impl<S, V> StoreWriteOps<V> for CachedStore<S>
+where
+    S: StoreWriteOps<V>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let key = self.cache_key(&node_key);
+        let result = self.inner.insert_branch(node_key, branch);
+        self.cache.invalidate(&key);
+        result
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.inner.insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        let result = self.inner.remove_branch(node_key);
+        self.cache.invalidate(&self.cache_key(node_key));
+        result
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.inner.remove_leaf(leaf_key)
+    }
+}
@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::sync::Arc;
 
 use rocksdb::prelude::*;
 use sparse_merkle_tree::{
@@ -7,7 +7,12 @@ use sparse_merkle_tree::{
     BranchKey, BranchNode, H256,
 };
 
-use crate::serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node};
+use crate::{
+    backend::{Backend, KeySpace},
+    format::{is_root_branch_key, open_format, StoreFormat, FORMAT_KEY},
+    namespace::Namespace,
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node},
+};
 
 /// A SMT `Store` implementation backed by a RocksDB database, using different column families to store the branches and the leaves.
 pub struct ColumnFamilyStore<'a, T, W> {
@@ -15,19 +20,81 @@ pub struct ColumnFamilyStore<'a, T, W> {
     inner: &'a T,
     branch_col: &'a ColumnFamily,
     leaf_col: &'a ColumnFamily,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<W>,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<&'a ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<&'a W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
 }
 
 impl<'a, T, W> ColumnFamilyStore<'a, T, W> {
     pub fn new(db: &'a T, branch_col: &'a ColumnFamily, leaf_col: &'a ColumnFamily) -> Self {
         ColumnFamilyStore {
             inner: db,
-            write_options: PhantomData,
+            read_options: None,
+            write_options: None,
+            record_format: false,
             branch_col,
             leaf_col,
         }
     }
+
+    /// Use `read_options` for every read of this store.
+    pub fn with_read_options(mut self, read_options: &'a ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: &'a W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(
+        db: &'a T,
+        branch_col: &'a ColumnFamily,
+        leaf_col: &'a ColumnFamily,
+    ) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(db, branch_col, leaf_col);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+}
+
+impl<'a, T, W> Backend for ColumnFamilyStore<'a, T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(Some(self.branch_col), &[])
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(Some(self.leaf_col), &[])
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
 }
 
 impl<'a, V, T, W> StoreReadOps<V> for ColumnFamilyStore<'a, T, W>
@@ -37,14 +104,19 @@ where
 {
     fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
         self.inner
-            .get_cf(self.branch_col, &branch_key_to_vec(branch_key))
-            .map(|s| s.map(|v| slice_to_branch_node(&v)))
-            .map_err(|e| Error::Store(e.to_string()))
+            .get_cf_full(
+                Some(self.branch_col),
+                branch_key_to_vec(branch_key),
+                self.read_options,
+            )
+            .map_err(|e| Error::Store(e.to_string()))?
+            .map(|v| slice_to_branch_node(&v).map_err(|e| Error::Store(e.to_string())))
+            .transpose()
     }
 
     fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
         self.inner
-            .get_cf(self.leaf_col, leaf_key.as_slice())
+            .get_cf_full(Some(self.leaf_col), leaf_key.as_slice(), self.read_options)
             .map(|s| s.map(|v| v.into()))
             .map_err(|e| Error::Store(e.to_string()))
     }
@@ -56,30 +128,51 @@ where
     T: DeleteCF<W> + PutCF<W>,
 {
     fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        if self.record_format && is_root_branch_key(&node_key) {
+            self.inner
+                .put_cf_full(
+                    Some(self.branch_col),
+                    FORMAT_KEY,
+                    StoreFormat::current().to_vec(),
+                    self.write_options,
+                )
+                .map_err(|e| Error::Store(e.to_string()))?;
+            self.record_format = false;
+        }
         self.inner
-            .put_cf(
-                self.branch_col,
-                &branch_key_to_vec(&node_key),
-                &branch_node_to_vec(&branch),
+            .put_cf_full(
+                Some(self.branch_col),
+                branch_key_to_vec(&node_key),
+                branch_node_to_vec(&branch),
+                self.write_options,
             )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
         self.inner
-            .put_cf(self.leaf_col, leaf_key.as_slice(), leaf)
+            .put_cf_full(
+                Some(self.leaf_col),
+                leaf_key.as_slice(),
+                leaf,
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
         self.inner
-            .delete_cf(self.branch_col, &branch_key_to_vec(node_key))
+            .delete_cf_full(
+                Some(self.branch_col),
+                branch_key_to_vec(node_key),
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
         self.inner
-            .delete_cf(self.leaf_col, leaf_key.as_slice())
+            .delete_cf_full(Some(self.leaf_col), leaf_key.as_slice(), self.write_options)
             .map_err(|e| Error::Store(e.to_string()))
     }
 }
@@ -92,8 +185,12 @@ pub struct ColumnFamilyStoreMultiTree<'a, T, W> {
     inner: &'a T,
     branch_col: &'a ColumnFamily,
     leaf_col: &'a ColumnFamily,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<W>,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<&'a ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<&'a W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
 }
 
 impl<'a, T, W> ColumnFamilyStoreMultiTree<'a, T, W> {
@@ -106,11 +203,80 @@ impl<'a, T, W> ColumnFamilyStoreMultiTree<'a, T, W> {
         ColumnFamilyStoreMultiTree {
             prefix,
             inner: db,
-            write_options: PhantomData,
+            read_options: None,
+            write_options: None,
+            record_format: false,
             branch_col,
             leaf_col,
         }
     }
+
+    /// Create a store for the tree of `namespace`, whose keys can not overlap the keys of other namespaces.
+    pub fn with_namespace(
+        namespace: &'a Namespace,
+        db: &'a T,
+        branch_col: &'a ColumnFamily,
+        leaf_col: &'a ColumnFamily,
+    ) -> Self {
+        Self::new(namespace.as_bytes(), db, branch_col, leaf_col)
+    }
+
+    /// Use `read_options` for every read of this store.
+    pub fn with_read_options(mut self, read_options: &'a ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: &'a W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(
+        prefix: &'a [u8],
+        db: &'a T,
+        branch_col: &'a ColumnFamily,
+        leaf_col: &'a ColumnFamily,
+    ) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(prefix, db, branch_col, leaf_col);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+}
+
+impl<'a, T, W> Backend for ColumnFamilyStoreMultiTree<'a, T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(Some(self.branch_col), self.prefix)
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(Some(self.leaf_col), self.prefix)
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
 }
 
 impl<'a, V, T, W> StoreReadOps<V> for ColumnFamilyStoreMultiTree<'a, T, W>
@@ -120,17 +286,23 @@ where
 {
     fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
         self.inner
-            .get_cf(
-                self.branch_col,
-                &[self.prefix, &branch_key_to_vec(branch_key)].concat(),
+            .get_cf_full(
+                Some(self.branch_col),
+                [self.prefix, &branch_key_to_vec(branch_key)].concat(),
+                self.read_options,
             )
-            .map(|s| s.map(|v| slice_to_branch_node(&v)))
-            .map_err(|e| Error::Store(e.to_string()))
+            .map_err(|e| Error::Store(e.to_string()))?
+            .map(|v| slice_to_branch_node(&v).map_err(|e| Error::Store(e.to_string())))
+            .transpose()
     }
 
     fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
         self.inner
-            .get_cf(self.leaf_col, &[self.prefix, leaf_key.as_slice()].concat())
+            .get_cf_full(
+                Some(self.leaf_col),
+                [self.prefix, leaf_key.as_slice()].concat(),
+                self.read_options,
+            )
             .map(|s| s.map(|v| v.into()))
             .map_err(|e| Error::Store(e.to_string()))
     }
@@ -142,40 +314,380 @@ where
     T: DeleteCF<W> + PutCF<W>,
 {
     fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        if self.record_format && is_root_branch_key(&node_key) {
+            self.inner
+                .put_cf_full(
+                    Some(self.branch_col),
+                    [self.prefix, FORMAT_KEY].concat(),
+                    StoreFormat::current().to_vec(),
+                    self.write_options,
+                )
+                .map_err(|e| Error::Store(e.to_string()))?;
+            self.record_format = false;
+        }
         self.inner
-            .put_cf(
-                self.branch_col,
-                &[self.prefix, &branch_key_to_vec(&node_key)].concat(),
-                &branch_node_to_vec(&branch),
+            .put_cf_full(
+                Some(self.branch_col),
+                [self.prefix, &branch_key_to_vec(&node_key)].concat(),
+                branch_node_to_vec(&branch),
+                self.write_options,
             )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
         self.inner
-            .put_cf(
-                self.leaf_col,
-                &[self.prefix, leaf_key.as_slice()].concat(),
+            .put_cf_full(
+                Some(self.leaf_col),
+                [self.prefix, leaf_key.as_slice()].concat(),
                 leaf,
+                self.write_options,
             )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
         self.inner
-            .delete_cf(
-                self.branch_col,
-                &[self.prefix, &branch_key_to_vec(node_key)].concat(),
+            .delete_cf_full(
+                Some(self.branch_col),
+                [self.prefix, &branch_key_to_vec(node_key)].concat(),
+                self.write_options,
             )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
         self.inner
-            .delete_cf(
-                self.leaf_col,
-                &[self.prefix, leaf_key.as_slice()].concat(),
+            .delete_cf_full(
+                Some(self.leaf_col),
+                [self.prefix, leaf_key.as_slice()].concat(),
+                self.write_options,
             )
             .map_err(|e| Error::Store(e.to_string()))
     }
 }
+
+/// Returns the handle of the column family `name`, or an error if it does not exist.
+fn cf_handle<'a, T: GetColumnFamilys>(db: &'a T, name: &str) -> Result<&'a ColumnFamily, Error> {
+    db.cf_handle(name)
+        .ok_or_else(|| Error::Store(format!("column family not found: {}", name)))
+}
+
+/// Returns the handle of the column family `name` for the key spaces of an owned store, which can not report
+/// an error. The column family was checked when the store was created, and can only be missing if it has been
+/// dropped since, the read and write ops of the store return an error in that case.
+fn space_cf_handle<'a, T: GetColumnFamilys>(db: &'a T, name: &str) -> &'a ColumnFamily {
+    cf_handle(db, name).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Check that the column families `names` exist in the database.
+fn check_cf_handles<T: GetColumnFamilys>(db: &T, names: &[&str]) -> Result<(), Error> {
+    for name in names {
+        cf_handle(db, name)?;
+    }
+    Ok(())
+}
+
+/// An owned variant of `ColumnFamilyStore`, holding a shared handle to the RocksDB database and the names of the column families.
+///
+/// It has no lifetime parameter, so a SMT using it can be kept in long-lived structs or moved into other threads.
+pub struct OwnedColumnFamilyStore<T, W> {
+    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` etc.
+    inner: Arc<T>,
+    branch_col: String,
+    leaf_col: String,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
+}
+
+impl<T: GetColumnFamilys, W> OwnedColumnFamilyStore<T, W> {
+    /// Create a store, returns an error if one of the column families does not exist.
+    pub fn new(db: Arc<T>, branch_col: &str, leaf_col: &str) -> Result<Self, Error> {
+        check_cf_handles(db.as_ref(), &[branch_col, leaf_col])?;
+        Ok(OwnedColumnFamilyStore {
+            inner: db,
+            branch_col: branch_col.to_string(),
+            leaf_col: leaf_col.to_string(),
+            read_options: None,
+            write_options: None,
+            record_format: false,
+        })
+    }
+
+    /// Use `read_options` for every read of this store.
+    ///
+    /// The options should not hold a snapshot, since the store may outlive it.
+    pub fn with_read_options(mut self, read_options: ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(db: Arc<T>, branch_col: &str, leaf_col: &str) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(db, branch_col, leaf_col)?;
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+
+    /// Returns a `ColumnFamilyStore` borrowing the database, the column families and the options of this store.
+    /// Returns an error if one of the column families has been dropped since the store was created.
+    pub fn as_store(&self) -> Result<ColumnFamilyStore<'_, T, W>, Error> {
+        Ok(ColumnFamilyStore {
+            read_options: self.read_options.as_ref(),
+            write_options: self.write_options.as_ref(),
+            record_format: self.record_format,
+            ..ColumnFamilyStore::new(
+                &self.inner,
+                cf_handle(self.inner.as_ref(), &self.branch_col)?,
+                cf_handle(self.inner.as_ref(), &self.leaf_col)?,
+            )
+        })
+    }
+}
+
+impl<T: GetColumnFamilys, W> Backend for OwnedColumnFamilyStore<T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        &self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options.as_ref()
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options.as_ref()
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(
+            Some(space_cf_handle(self.inner.as_ref(), &self.branch_col)),
+            &[],
+        )
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(
+            Some(space_cf_handle(self.inner.as_ref(), &self.leaf_col)),
+            &[],
+        )
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
+}
+
+impl<V, T, W> StoreReadOps<V> for OwnedColumnFamilyStore<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: GetCF<ReadOptions> + GetColumnFamilys,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        StoreReadOps::<V>::get_branch(&self.as_store()?, branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.as_store()?.get_leaf(leaf_key)
+    }
+}
+
+impl<V, T, W> StoreWriteOps<V> for OwnedColumnFamilyStore<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: DeleteCF<W> + PutCF<W> + GetColumnFamilys,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let mut store = self.as_store()?;
+        StoreWriteOps::<V>::insert_branch(&mut store, node_key, branch)?;
+        self.record_format = store.record_format;
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.as_store()?.insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_branch(&mut self.as_store()?, node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_leaf(&mut self.as_store()?, leaf_key)
+    }
+}
+
+/// An owned variant of `ColumnFamilyStoreMultiTree`, holding a shared handle to the RocksDB database,
+/// its own copy of the prefix and the names of the column families.
+pub struct OwnedColumnFamilyStoreMultiTree<T, W> {
+    // A key prefix to distinguish different trees.
+    prefix: Vec<u8>,
+    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` etc.
+    inner: Arc<T>,
+    branch_col: String,
+    leaf_col: String,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
+}
+
+impl<T: GetColumnFamilys, W> OwnedColumnFamilyStoreMultiTree<T, W> {
+    /// Create a store, returns an error if one of the column families does not exist.
+    pub fn new(prefix: &[u8], db: Arc<T>, branch_col: &str, leaf_col: &str) -> Result<Self, Error> {
+        check_cf_handles(db.as_ref(), &[branch_col, leaf_col])?;
+        Ok(OwnedColumnFamilyStoreMultiTree {
+            prefix: prefix.to_vec(),
+            inner: db,
+            branch_col: branch_col.to_string(),
+            leaf_col: leaf_col.to_string(),
+            read_options: None,
+            write_options: None,
+            record_format: false,
+        })
+    }
+
+    /// Create a store for the tree of `namespace`, whose keys can not overlap the keys of other namespaces.
+    /// Returns an error if one of the column families does not exist.
+    pub fn with_namespace(
+        namespace: &Namespace,
+        db: Arc<T>,
+        branch_col: &str,
+        leaf_col: &str,
+    ) -> Result<Self, Error> {
+        Self::new(namespace.as_bytes(), db, branch_col, leaf_col)
+    }
+
+    /// Use `read_options` for every read of this store.
+    ///
+    /// The options should not hold a snapshot, since the store may outlive it.
+    pub fn with_read_options(mut self, read_options: ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(prefix: &[u8], db: Arc<T>, branch_col: &str, leaf_col: &str) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(prefix, db, branch_col, leaf_col)?;
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+
+    /// Returns a `ColumnFamilyStoreMultiTree` borrowing the database, the prefix, the column families and the options of this store.
+    /// Returns an error if one of the column families has been dropped since the store was created.
+    pub fn as_store(&self) -> Result<ColumnFamilyStoreMultiTree<'_, T, W>, Error> {
+        Ok(ColumnFamilyStoreMultiTree {
+            read_options: self.read_options.as_ref(),
+            write_options: self.write_options.as_ref(),
+            record_format: self.record_format,
+            ..ColumnFamilyStoreMultiTree::new(
+                &self.prefix,
+                &self.inner,
+                cf_handle(self.inner.as_ref(), &self.branch_col)?,
+                cf_handle(self.inner.as_ref(), &self.leaf_col)?,
+            )
+        })
+    }
+}
+
+impl<T: GetColumnFamilys, W> Backend for OwnedColumnFamilyStoreMultiTree<T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        &self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options.as_ref()
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options.as_ref()
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(
+            Some(space_cf_handle(self.inner.as_ref(), &self.branch_col)),
+            &self.prefix,
+        )
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(
+            Some(space_cf_handle(self.inner.as_ref(), &self.leaf_col)),
+            &self.prefix,
+        )
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
+}
+
+impl<V, T, W> StoreReadOps<V> for OwnedColumnFamilyStoreMultiTree<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: GetCF<ReadOptions> + GetColumnFamilys,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        StoreReadOps::<V>::get_branch(&self.as_store()?, branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.as_store()?.get_leaf(leaf_key)
+    }
+}
+
+impl<V, T, W> StoreWriteOps<V> for OwnedColumnFamilyStoreMultiTree<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: DeleteCF<W> + PutCF<W> + GetColumnFamilys,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let mut store = self.as_store()?;
+        StoreWriteOps::<V>::insert_branch(&mut store, node_key, branch)?;
+        self.record_format = store.record_format;
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.as_store()?.insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_branch(&mut self.as_store()?, node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_leaf(&mut self.as_store()?, leaf_key)
+    }
+}
@@ -1,29 +1,155 @@
-use std::marker::PhantomData;
+use std::sync::Arc;
 
-use rocksdb::prelude::*;
+use rocksdb::{prelude::*, WriteBatch};
 use sparse_merkle_tree::{
     error::Error,
     traits::{StoreReadOps, StoreWriteOps, Value},
     BranchKey, BranchNode, H256,
 };
 
-use crate::serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node};
+use crate::{
+    backend::{Backend, KeySpace},
+    format::{is_root_branch_key, open_format, StoreFormat, FORMAT_KEY},
+    journal::JOURNAL_PREFIX,
+    namespace::Namespace,
+    root::ROOT_KEY,
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node},
+};
+
+/// The first byte of the branch keys and the metadata records of a `DefaultStore` with `KeyLayout::Tagged`.
+pub const BRANCH_KEY_TAG: u8 = 0x00;
+/// The first byte of the leaf keys of a `DefaultStore` with `KeyLayout::Tagged`.
+pub const LEAF_KEY_TAG: u8 = 0xff;
+
+const TAGGED_BRANCH_PREFIX: &[u8] = &[BRANCH_KEY_TAG];
+const TAGGED_LEAF_PREFIX: &[u8] = &[LEAF_KEY_TAG];
+// The number of records rewritten in a write batch by `convert_to_tagged`.
+const CONVERT_BATCH_SIZE: usize = 10_000;
+
+/// The layout of the keys of a `DefaultStore` in the default column family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyLayout {
+    /// A leaf is stored under its 32 bytes key and a branch under the 33 bytes of `serde::branch_key_to_vec`,
+    /// so they are told apart by the key length only.
+    Plain,
+    /// Every key starts with a one byte tag, `BRANCH_KEY_TAG` for the branches and the metadata records and
+    /// `LEAF_KEY_TAG` for the leaves, so the branches and the leaves are disjoint ranges of keys.
+    Tagged,
+}
+
+impl KeyLayout {
+    fn branch_prefix(self) -> &'static [u8] {
+        match self {
+            KeyLayout::Plain => &[],
+            KeyLayout::Tagged => TAGGED_BRANCH_PREFIX,
+        }
+    }
+
+    fn leaf_prefix(self) -> &'static [u8] {
+        match self {
+            KeyLayout::Plain => &[],
+            KeyLayout::Tagged => TAGGED_LEAF_PREFIX,
+        }
+    }
+}
 
 /// A SMT `Store` implementation backed by a RocksDB database, using the default column family.
 pub struct DefaultStore<'a, T, W> {
     // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` / `Snapshot` etc.
     inner: &'a T,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<W>,
+    key_layout: KeyLayout,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<&'a ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<&'a W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
 }
 
 impl<'a, T, W> DefaultStore<'a, T, W> {
     pub fn new(db: &'a T) -> Self {
         DefaultStore {
             inner: db,
-            write_options: PhantomData,
+            key_layout: KeyLayout::Plain,
+            read_options: None,
+            write_options: None,
+            record_format: false,
+        }
+    }
+
+    /// Create a store with `KeyLayout::Tagged` keys.
+    pub fn tagged(db: &'a T) -> Self {
+        DefaultStore {
+            key_layout: KeyLayout::Tagged,
+            ..Self::new(db)
         }
     }
+
+    pub fn key_layout(&self) -> KeyLayout {
+        self.key_layout
+    }
+
+    /// Use `read_options` for every read of this store.
+    pub fn with_read_options(mut self, read_options: &'a ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: &'a W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(db: &'a T) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(db);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+
+    /// Create a store with `KeyLayout::Tagged` keys and check the format of the data like `open`.
+    pub fn open_tagged(db: &'a T) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::tagged(db);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+}
+
+impl<'a, T, W> Backend for DefaultStore<'a, T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.key_layout.branch_prefix())
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.key_layout.leaf_prefix())
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
 }
 
 impl<'a, V, T, W> StoreReadOps<V> for DefaultStore<'a, T, W>
@@ -33,14 +159,21 @@ where
 {
     fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
         self.inner
-            .get(&branch_key_to_vec(branch_key))
-            .map(|s| s.map(|v| slice_to_branch_node(&v)))
-            .map_err(|e| Error::Store(e.to_string()))
+            .get_full(
+                self.branch_space().key(&branch_key_to_vec(branch_key)),
+                self.read_options,
+            )
+            .map_err(|e| Error::Store(e.to_string()))?
+            .map(|v| slice_to_branch_node(&v).map_err(|e| Error::Store(e.to_string())))
+            .transpose()
     }
 
     fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
         self.inner
-            .get(leaf_key.as_slice())
+            .get_full(
+                self.leaf_space().key(leaf_key.as_slice()),
+                self.read_options,
+            )
             .map(|s| s.map(|v| v.into()))
             .map_err(|e| Error::Store(e.to_string()))
     }
@@ -52,28 +185,115 @@ where
     T: Delete<W> + Put<W>,
 {
     fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        if self.record_format && is_root_branch_key(&node_key) {
+            self.inner
+                .put_full(
+                    self.branch_space().key(FORMAT_KEY),
+                    StoreFormat::current().to_vec(),
+                    self.write_options,
+                )
+                .map_err(|e| Error::Store(e.to_string()))?;
+            self.record_format = false;
+        }
         self.inner
-            .put(&branch_key_to_vec(&node_key), &branch_node_to_vec(&branch))
+            .put_full(
+                self.branch_space().key(&branch_key_to_vec(&node_key)),
+                branch_node_to_vec(&branch),
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
         self.inner
-            .put(leaf_key.as_slice(), leaf)
+            .put_full(
+                self.leaf_space().key(leaf_key.as_slice()),
+                leaf,
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
         self.inner
-            .delete(&branch_key_to_vec(node_key))
+            .delete_full(
+                self.branch_space().key(&branch_key_to_vec(node_key)),
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
         self.inner
-            .delete(leaf_key.as_slice())
+            .delete_full(
+                self.leaf_space().key(leaf_key.as_slice()),
+                self.write_options,
+            )
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+}
+
+/// Rewrite the records of a `DefaultStore` from `KeyLayout::Plain` keys to `KeyLayout::Tagged` keys in place,
+/// in write batches. Returns the number of rewritten records.
+///
+/// The default column family must only hold the records of the store, other keys of the lengths of the store
+/// keys would be rewritten too. An interrupted conversion can be run again, the store must not be used until
+/// the conversion is complete.
+pub fn convert_to_tagged<T>(db: &T) -> Result<usize, Error>
+where
+    T: GetCF<ReadOptions> + IterateCF + WriteOps,
+{
+    let plain_space = KeySpace::new(None, &[]);
+    let branch_space = KeySpace::new(None, TAGGED_BRANCH_PREFIX);
+    let leaf_space = KeySpace::new(None, TAGGED_LEAF_PREFIX);
+    let write = |batch: &WriteBatch| {
+        db.write_full(batch, None)
             .map_err(|e| Error::Store(e.to_string()))
+    };
+
+    // The root branch is the only plain branch whose key starts with `LEAF_KEY_TAG`, it is rewritten first, so the
+    // other plain branches are the 33 bytes keys which do not start with `LEAF_KEY_TAG`.
+    let mut converted = 0;
+    let root_key = branch_key_to_vec(&BranchKey::new(u8::MAX, H256::zero()));
+    if branch_space.get(db, &root_key, None)?.is_none() {
+        if let Some(value) = plain_space.get(db, &root_key, None)? {
+            let mut batch = WriteBatch::default();
+            branch_space.batch_put(&mut batch, &root_key, &value)?;
+            plain_space.batch_delete(&mut batch, &root_key)?;
+            write(&batch)?;
+            converted += 1;
+        }
     }
+
+    let mut batch = WriteBatch::default();
+    let mut batch_len = 0;
+    for (key, value) in plain_space.scan(db, None)? {
+        let space = match key.len() {
+            32 => &leaf_space,
+            33 if key[0] != LEAF_KEY_TAG => &branch_space,
+            _ if key.as_ref() == ROOT_KEY
+                || key.as_ref() == FORMAT_KEY
+                || key.starts_with(JOURNAL_PREFIX) =>
+            {
+                &branch_space
+            }
+            _ => continue,
+        };
+        space.batch_put(&mut batch, &key, &value)?;
+        plain_space.batch_delete(&mut batch, &key)?;
+        batch_len += 1;
+        if batch_len == CONVERT_BATCH_SIZE {
+            write(&batch)?;
+            converted += batch_len;
+            batch = WriteBatch::default();
+            batch_len = 0;
+        }
+    }
+    if batch_len > 0 {
+        write(&batch)?;
+        converted += batch_len;
+    }
+    Ok(converted)
 }
 
 /// A SMT `Store` implementation backed by a RocksDB database, using the default column family and supports multiple trees.
@@ -82,8 +302,12 @@ pub struct DefaultStoreMultiTree<'a, T, W> {
     prefix: &'a [u8],
     // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` / `Snapshot` etc.
     inner: &'a T,
-    // A generic write options, can be a `WriteOptions` / `()` etc.
-    write_options: PhantomData<W>,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<&'a ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<&'a W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
 }
 
 impl<'a, T, W> DefaultStoreMultiTree<'a, T, W> {
@@ -91,9 +315,68 @@ impl<'a, T, W> DefaultStoreMultiTree<'a, T, W> {
         DefaultStoreMultiTree {
             prefix,
             inner: db,
-            write_options: PhantomData,
+            read_options: None,
+            write_options: None,
+            record_format: false,
         }
     }
+
+    /// Create a store for the tree of `namespace`, whose keys can not overlap the keys of other namespaces.
+    pub fn with_namespace(namespace: &'a Namespace, db: &'a T) -> Self {
+        Self::new(namespace.as_bytes(), db)
+    }
+
+    /// Use `read_options` for every read of this store.
+    pub fn with_read_options(mut self, read_options: &'a ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: &'a W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(prefix: &'a [u8], db: &'a T) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(prefix, db);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+}
+
+impl<'a, T, W> Backend for DefaultStoreMultiTree<'a, T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.prefix)
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.prefix)
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
 }
 
 impl<'a, V, T, W> StoreReadOps<V> for DefaultStoreMultiTree<'a, T, W>
@@ -103,14 +386,21 @@ where
 {
     fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
         self.inner
-            .get(&[self.prefix, &branch_key_to_vec(branch_key)].concat())
-            .map(|s| s.map(|v| slice_to_branch_node(&v)))
-            .map_err(|e| Error::Store(e.to_string()))
+            .get_full(
+                [self.prefix, &branch_key_to_vec(branch_key)].concat(),
+                self.read_options,
+            )
+            .map_err(|e| Error::Store(e.to_string()))?
+            .map(|v| slice_to_branch_node(&v).map_err(|e| Error::Store(e.to_string())))
+            .transpose()
     }
 
     fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
         self.inner
-            .get(&[self.prefix, leaf_key.as_slice()].concat())
+            .get_full(
+                [self.prefix, leaf_key.as_slice()].concat(),
+                self.read_options,
+            )
             .map(|s| s.map(|v| v.into()))
             .map_err(|e| Error::Store(e.to_string()))
     }
@@ -122,29 +412,337 @@ where
     T: Delete<W> + Put<W>,
 {
     fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        if self.record_format && is_root_branch_key(&node_key) {
+            self.inner
+                .put_full(
+                    [self.prefix, FORMAT_KEY].concat(),
+                    StoreFormat::current().to_vec(),
+                    self.write_options,
+                )
+                .map_err(|e| Error::Store(e.to_string()))?;
+            self.record_format = false;
+        }
         self.inner
-            .put(
-                &[self.prefix, &branch_key_to_vec(&node_key)].concat(),
-                &branch_node_to_vec(&branch),
+            .put_full(
+                [self.prefix, &branch_key_to_vec(&node_key)].concat(),
+                branch_node_to_vec(&branch),
+                self.write_options,
             )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
         self.inner
-            .put(&[self.prefix, leaf_key.as_slice()].concat(), leaf)
+            .put_full(
+                [self.prefix, leaf_key.as_slice()].concat(),
+                leaf,
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
         self.inner
-            .delete(&[self.prefix, &branch_key_to_vec(node_key)].concat())
+            .delete_full(
+                [self.prefix, &branch_key_to_vec(node_key)].concat(),
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 
     fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
         self.inner
-            .delete(&[self.prefix, leaf_key.as_slice()].concat())
+            .delete_full(
+                [self.prefix, leaf_key.as_slice()].concat(),
+                self.write_options,
+            )
             .map_err(|e| Error::Store(e.to_string()))
     }
 }
+
+/// An owned variant of `DefaultStore`, holding a shared handle to the RocksDB database.
+///
+/// It has no lifetime parameter, so a SMT using it can be kept in long-lived structs or moved into other threads.
+pub struct OwnedDefaultStore<T, W> {
+    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` etc.
+    inner: Arc<T>,
+    key_layout: KeyLayout,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
+}
+
+impl<T, W> OwnedDefaultStore<T, W> {
+    pub fn new(db: Arc<T>) -> Self {
+        OwnedDefaultStore {
+            inner: db,
+            key_layout: KeyLayout::Plain,
+            read_options: None,
+            write_options: None,
+            record_format: false,
+        }
+    }
+
+    /// Create a store with `KeyLayout::Tagged` keys.
+    pub fn tagged(db: Arc<T>) -> Self {
+        OwnedDefaultStore {
+            key_layout: KeyLayout::Tagged,
+            ..Self::new(db)
+        }
+    }
+
+    pub fn key_layout(&self) -> KeyLayout {
+        self.key_layout
+    }
+
+    /// Use `read_options` for every read of this store.
+    ///
+    /// The options should not hold a snapshot, since the store may outlive it.
+    pub fn with_read_options(mut self, read_options: ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(db: Arc<T>) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(db);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+
+    /// Create a store with `KeyLayout::Tagged` keys and check the format of the data like `open`.
+    pub fn open_tagged(db: Arc<T>) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::tagged(db);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+
+    /// Returns a `DefaultStore` borrowing the database and the options of this store.
+    pub fn as_store(&self) -> DefaultStore<'_, T, W> {
+        DefaultStore {
+            key_layout: self.key_layout,
+            read_options: self.read_options.as_ref(),
+            write_options: self.write_options.as_ref(),
+            record_format: self.record_format,
+            ..DefaultStore::new(&self.inner)
+        }
+    }
+}
+
+impl<T, W> Backend for OwnedDefaultStore<T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        &self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options.as_ref()
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options.as_ref()
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.key_layout.branch_prefix())
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.key_layout.leaf_prefix())
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
+}
+
+impl<V, T, W> StoreReadOps<V> for OwnedDefaultStore<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: Get<ReadOptions>,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        StoreReadOps::<V>::get_branch(&self.as_store(), branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.as_store().get_leaf(leaf_key)
+    }
+}
+
+impl<V, T, W> StoreWriteOps<V> for OwnedDefaultStore<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: Delete<W> + Put<W>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let mut store = self.as_store();
+        StoreWriteOps::<V>::insert_branch(&mut store, node_key, branch)?;
+        self.record_format = store.record_format;
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.as_store().insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_branch(&mut self.as_store(), node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_leaf(&mut self.as_store(), leaf_key)
+    }
+}
+
+/// An owned variant of `DefaultStoreMultiTree`, holding a shared handle to the RocksDB database and its own copy of the prefix.
+pub struct OwnedDefaultStoreMultiTree<T, W> {
+    // A key prefix to distinguish different trees.
+    prefix: Vec<u8>,
+    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransactionDB` etc.
+    inner: Arc<T>,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<W>,
+    // Set by `open` if the store has no format record, which is then written along with the next root branch.
+    record_format: bool,
+}
+
+impl<T, W> OwnedDefaultStoreMultiTree<T, W> {
+    pub fn new(prefix: &[u8], db: Arc<T>) -> Self {
+        OwnedDefaultStoreMultiTree {
+            prefix: prefix.to_vec(),
+            inner: db,
+            read_options: None,
+            write_options: None,
+            record_format: false,
+        }
+    }
+
+    /// Create a store for the tree of `namespace`, whose keys can not overlap the keys of other namespaces.
+    pub fn with_namespace(namespace: &Namespace, db: Arc<T>) -> Self {
+        Self::new(namespace.as_bytes(), db)
+    }
+
+    /// Use `read_options` for every read of this store.
+    ///
+    /// The options should not hold a snapshot, since the store may outlive it.
+    pub fn with_read_options(mut self, read_options: ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    /// Create a store and check that the data in the database was written in a format this build can read,
+    /// see `format::check_format`.
+    pub fn open(prefix: &[u8], db: Arc<T>) -> Result<Self, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        let mut store = Self::new(prefix, db);
+        store.record_format = open_format(&store)?;
+        Ok(store)
+    }
+
+    /// Returns a `DefaultStoreMultiTree` borrowing the database, the prefix and the options of this store.
+    pub fn as_store(&self) -> DefaultStoreMultiTree<'_, T, W> {
+        DefaultStoreMultiTree {
+            read_options: self.read_options.as_ref(),
+            write_options: self.write_options.as_ref(),
+            record_format: self.record_format,
+            ..DefaultStoreMultiTree::new(&self.prefix, &self.inner)
+        }
+    }
+}
+
+impl<T, W> Backend for OwnedDefaultStoreMultiTree<T, W> {
+    type DB = T;
+    type WriteOptions = W;
+
+    fn db(&self) -> &T {
+        &self.inner
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.read_options.as_ref()
+    }
+
+    fn write_options(&self) -> Option<&W> {
+        self.write_options.as_ref()
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, &self.prefix)
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, &self.prefix)
+    }
+
+    fn records_format(&self) -> bool {
+        self.record_format
+    }
+}
+
+impl<V, T, W> StoreReadOps<V> for OwnedDefaultStoreMultiTree<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: Get<ReadOptions>,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        StoreReadOps::<V>::get_branch(&self.as_store(), branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.as_store().get_leaf(leaf_key)
+    }
+}
+
+impl<V, T, W> StoreWriteOps<V> for OwnedDefaultStoreMultiTree<T, W>
+where
+    V: Value + AsRef<[u8]> + From<DBVector>,
+    T: Delete<W> + Put<W>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        let mut store = self.as_store();
+        StoreWriteOps::<V>::insert_branch(&mut store, node_key, branch)?;
+        self.record_format = store.record_format;
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.as_store().insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_branch(&mut self.as_store(), node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        StoreWriteOps::<V>::remove_leaf(&mut self.as_store(), leaf_key)
+    }
+}
@@ -0,0 +1,220 @@
+use rocksdb::{
+    prelude::{DeleteCF, IterateCF, PutCF, WriteOps},
+    ColumnFamily, Direction, Handle, IteratorMode, OptimisticTransaction, OptimisticTransactionDB,
+    ReadOptions, Transaction, WriteBatch, WriteOptions, DB,
+};
+use sparse_merkle_tree::{error::Error, H256};
+
+use crate::{
+    backend::{prefix_end, Backend, KeySpace},
+    root::write_root,
+};
+
+/// Delete all keys of a range, with a RocksDB range delete where the database supports it.
+pub trait DeleteRange<W> {
+    /// Delete the keys in `[from, to)` of the column family `col`, `None` for the default column family.
+    fn delete_range_cf_full(
+        &self,
+        col: Option<&ColumnFamily>,
+        from: &[u8],
+        to: &[u8],
+        write_options: Option<&W>,
+    ) -> Result<(), Error>;
+}
+
+fn write_delete_range<T: WriteOps>(
+    db: &T,
+    col: Option<&ColumnFamily>,
+    from: &[u8],
+    to: &[u8],
+    write_options: Option<&WriteOptions>,
+) -> Result<(), Error> {
+    let mut batch = WriteBatch::default();
+    match col {
+        Some(col) => batch.delete_range_cf(col, from, to),
+        None => batch.delete_range(from, to),
+    }
+    .and_then(|_| db.write_full(&batch, write_options))
+    .map_err(|e| Error::Store(e.to_string()))
+}
+
+impl DeleteRange<WriteOptions> for DB {
+    fn delete_range_cf_full(
+        &self,
+        col: Option<&ColumnFamily>,
+        from: &[u8],
+        to: &[u8],
+        write_options: Option<&WriteOptions>,
+    ) -> Result<(), Error> {
+        write_delete_range(self, col, from, to, write_options)
+    }
+}
+
+impl DeleteRange<WriteOptions> for OptimisticTransactionDB {
+    fn delete_range_cf_full(
+        &self,
+        col: Option<&ColumnFamily>,
+        from: &[u8],
+        to: &[u8],
+        write_options: Option<&WriteOptions>,
+    ) -> Result<(), Error> {
+        write_delete_range(self, col, from, to, write_options)
+    }
+}
+
+// Transactions do not support range deletes, so the keys are deleted one by one.
+fn delete_keys<T>(db: &T, col: Option<&ColumnFamily>, from: &[u8], to: &[u8]) -> Result<(), Error>
+where
+    T: IterateCF + DeleteCF<()>,
+{
+    let mode = IteratorMode::From(from, Direction::Forward);
+    let read_options = ReadOptions::default();
+    let iter = match col {
+        Some(col) => db
+            .iterator_cf_opt(col, mode, &read_options)
+            .map_err(|e| Error::Store(e.to_string()))?,
+        None => db.iterator_opt(mode, &read_options),
+    };
+    let keys: Vec<Box<[u8]>> = iter
+        .map(|(key, _)| key)
+        .take_while(|key| key.as_ref() < to)
+        .collect();
+    for key in keys {
+        db.delete_cf_full(col, key, None)
+            .map_err(|e| Error::Store(e.to_string()))?;
+    }
+    Ok(())
+}
+
+impl DeleteRange<()> for OptimisticTransaction {
+    fn delete_range_cf_full(
+        &self,
+        col: Option<&ColumnFamily>,
+        from: &[u8],
+        to: &[u8],
+        _write_options: Option<&()>,
+    ) -> Result<(), Error> {
+        delete_keys(self, col, from, to)
+    }
+}
+
+impl<'a, T> DeleteRange<()> for Transaction<'a, T> {
+    fn delete_range_cf_full(
+        &self,
+        col: Option<&ColumnFamily>,
+        from: &[u8],
+        to: &[u8],
+        _write_options: Option<&()>,
+    ) -> Result<(), Error> {
+        delete_keys(self, col, from, to)
+    }
+}
+
+fn delete_space<T, W>(
+    db: &T,
+    space: KeySpace,
+    read_options: Option<&ReadOptions>,
+    write_options: Option<&W>,
+) -> Result<(), Error>
+where
+    T: IterateCF + DeleteRange<W>,
+{
+    let end = match prefix_end(space.prefix) {
+        Some(end) => end,
+        // an empty prefix or a prefix of `0xff` bytes, the range ends right after the last key
+        None => {
+            let default_read_options = ReadOptions::default();
+            let read_options = read_options.unwrap_or(&default_read_options);
+            let mut iter = match space.col {
+                Some(col) => db
+                    .iterator_cf_opt(col, IteratorMode::End, read_options)
+                    .map_err(|e| Error::Store(e.to_string()))?,
+                None => db.iterator_opt(IteratorMode::End, read_options),
+            };
+            match iter.next() {
+                Some((key, _)) if key.starts_with(space.prefix) => [&key[..], &[0]].concat(),
+                _ => return Ok(()),
+            }
+        }
+    };
+    db.delete_range_cf_full(space.col, space.prefix, &end, write_options)
+}
+
+// The key spaces of a store, without a space included in the other one, e.g. the leaf space of
+// a `DefaultStoreMultiTree`, which is its branch space.
+fn store_spaces<S: Backend>(store: &S) -> Vec<KeySpace<'_>> {
+    let (branch_space, leaf_space) = (store.branch_space(), store.leaf_space());
+    let same_col = match (branch_space.col, leaf_space.col) {
+        (Some(branch_col), Some(leaf_col)) => branch_col.handle() == leaf_col.handle(),
+        (None, None) => true,
+        _ => false,
+    };
+    if same_col && leaf_space.prefix.starts_with(branch_space.prefix) {
+        vec![branch_space]
+    } else if same_col && branch_space.prefix.starts_with(leaf_space.prefix) {
+        vec![leaf_space]
+    } else {
+        vec![branch_space, leaf_space]
+    }
+}
+
+/// Delete all records of a store, including the root and format records.
+///
+/// Refuses a store in the default column family without a prefix, e.g. a `DefaultStore`, whose records are every key
+/// of the default column family, unless `allow_default_column_family`.
+pub(crate) fn delete_store<S>(store: &S, allow_default_column_family: bool) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: IterateCF + DeleteRange<S::WriteOptions>,
+{
+    let spaces = store_spaces(store);
+    if !allow_default_column_family
+        && spaces
+            .iter()
+            .any(|space| space.col.is_none() && space.prefix.is_empty())
+    {
+        return Err(Error::Store(
+            "the store has no prefix in the default column family, deleting it would delete the whole column family"
+                .to_string(),
+        ));
+    }
+    for space in spaces {
+        delete_space(
+            store.db(),
+            space,
+            store.read_options(),
+            store.write_options(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Delete all branches and leaves of the tree of a store and reset its committed root to zero,
+/// without recomputing the tree.
+///
+/// On a `DB` or an `OptimisticTransactionDB` the keys are deleted with range deletes, which delete every key
+/// starting with the prefix of the store, e.g. the keys of a tree with the raw prefix `tree1` for the prefix `tree`.
+/// Use `namespace::Namespace` prefixes to keep trees apart. A transaction deletes the keys one by one.
+/// If interrupted, `drop_tree` can be run again.
+///
+/// Returns an error for a store in the default column family without a prefix, e.g. a `DefaultStore`,
+/// see `drop_unprefixed_tree`.
+pub fn drop_tree<S>(store: &S) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: IterateCF + DeleteRange<S::WriteOptions> + PutCF<S::WriteOptions>,
+{
+    delete_store(store, false)?;
+    write_root(store, &H256::zero())
+}
+
+/// Like `drop_tree`, but also drops the tree of a store in the default column family without a prefix,
+/// e.g. a `DefaultStore`, which deletes every key of the default column family, including the keys of other stores.
+pub fn drop_unprefixed_tree<S>(store: &S) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: IterateCF + DeleteRange<S::WriteOptions> + PutCF<S::WriteOptions>,
+{
+    delete_store(store, true)?;
+    write_root(store, &H256::zero())
+}
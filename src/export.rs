@@ -0,0 +1,276 @@
+use std::io::Write;
+
+use rocksdb::{
+    prelude::{GetCF, IterateCF},
+    Direction, ReadOptions,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, error::Error, merge::merge, traits::Hasher, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::Backend,
+    format::StoreFormat,
+    iter::StoreIterOps,
+    root::read_root,
+    serde::{branch_key_to_vec, branch_node_to_vec},
+};
+
+/// The magic bytes at the start of an export.
+pub const EXPORT_MAGIC: &[u8; 8] = b"SMTEXPRT";
+/// The format version written by `export_store`.
+pub const EXPORT_FORMAT_VERSION: u8 = 1;
+
+// The flag of the header of an export which includes the branches.
+const WITH_BRANCHES: u8 = 1;
+
+/// The identifier of a hasher, recorded in an export so that it is imported with the same hasher.
+pub trait HasherId {
+    const HASHER_ID: u8;
+}
+
+impl HasherId for Blake2bHasher {
+    const HASHER_ID: u8 = 1;
+}
+
+/// The header of an export.
+///
+/// An export is the header, the leaves in the byte order of their keys, each a key followed by the length of the
+/// value as a big-endian u32 and the value, the branches if exported, each an encoded branch key followed by the
+/// length and the encoded branch node, then a big-endian CRC-32 of all previous bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportHeader {
+    pub version: u8,
+    /// See `HasherId`.
+    pub hasher_id: u8,
+    /// The format of the exported branch nodes.
+    pub store_format: StoreFormat,
+    pub root: H256,
+    /// The number of leaves.
+    pub leaves: u64,
+    /// The number of branches, `None` if the branches are not exported.
+    pub branches: Option<u64>,
+}
+
+impl ExportHeader {
+    /// The length of an encoded header.
+    pub const LEN: usize = 61;
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(Self::LEN);
+        ret.extend_from_slice(EXPORT_MAGIC);
+        ret.push(self.version);
+        ret.push(self.hasher_id);
+        ret.extend_from_slice(&self.store_format.to_vec());
+        ret.push(if self.branches.is_some() {
+            WITH_BRANCHES
+        } else {
+            0
+        });
+        ret.extend_from_slice(self.root.as_slice());
+        ret.extend_from_slice(&self.leaves.to_be_bytes());
+        ret.extend_from_slice(&self.branches.unwrap_or(0).to_be_bytes());
+        ret
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Result<Self, Error> {
+        if slice.len() != Self::LEN || &slice[..8] != EXPORT_MAGIC {
+            return Err(Error::Store(format!("invalid export header: {:?}", slice)));
+        }
+        let root: [u8; 32] = slice[13..45].try_into().expect("checked header length");
+        let leaves = u64::from_be_bytes(slice[45..53].try_into().expect("checked header length"));
+        let branches = u64::from_be_bytes(slice[53..61].try_into().expect("checked header length"));
+        Ok(ExportHeader {
+            version: slice[8],
+            hasher_id: slice[9],
+            store_format: StoreFormat::from_slice(&slice[10..12])?,
+            root: root.into(),
+            leaves,
+            branches: (slice[12] & WITH_BRANCHES != 0).then_some(branches),
+        })
+    }
+}
+
+// The CRC-32 (IEEE) lookup table.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-32 checksum of an export.
+#[derive(Debug, Clone)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = CRC32_TABLE[((self.0 ^ u32::from(*byte)) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+// Writes an export and its checksum.
+struct ExportWriter<W> {
+    inner: W,
+    checksum: Crc32,
+}
+
+impl<W: Write> ExportWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.checksum.update(bytes);
+        self.inner
+            .write_all(bytes)
+            .map_err(|e| Error::Store(e.to_string()))
+    }
+
+    fn write_record(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.write(key)?;
+        self.write(&(value.len() as u32).to_be_bytes())?;
+        self.write(value)
+    }
+
+    fn finish(mut self) -> Result<(), Error> {
+        let checksum = self.checksum.finish().to_be_bytes();
+        self.write(&checksum)?;
+        self.inner.flush().map_err(|e| Error::Store(e.to_string()))
+    }
+}
+
+/// Export the tree of a store at `root` to `writer`, with its branches if `with_branches`, returns the header.
+///
+/// The store is read twice, to count then to write the records, so it should read from a RocksDB snapshot
+/// while the database is written; an error is returned if the number of records changes between the reads.
+pub fn export_root<H, S, W>(
+    store: &S,
+    root: &H256,
+    writer: W,
+    with_branches: bool,
+) -> Result<ExportHeader, Error>
+where
+    H: HasherId,
+    S: StoreIterOps,
+    W: Write,
+{
+    let leaves = store.leaves::<Box<[u8]>>(None, Direction::Forward)?.count() as u64;
+    let branches = if with_branches {
+        Some(store.branches(None)?.count() as u64)
+    } else {
+        None
+    };
+    let header = ExportHeader {
+        version: EXPORT_FORMAT_VERSION,
+        hasher_id: H::HASHER_ID,
+        store_format: StoreFormat::current(),
+        root: *root,
+        leaves,
+        branches,
+    };
+
+    let mut writer = ExportWriter {
+        inner: writer,
+        checksum: Crc32::new(),
+    };
+    writer.write(&header.to_vec())?;
+    let mut written = 0;
+    for (key, value) in store.leaves::<Box<[u8]>>(None, Direction::Forward)? {
+        writer.write_record(key.as_slice(), &value)?;
+        written += 1;
+    }
+    check_count("leaves", leaves, written)?;
+    if let Some(branches) = branches {
+        let mut written = 0;
+        for branch in store.branches(None)? {
+            let (branch_key, branch) = branch?;
+            writer.write_record(
+                &branch_key_to_vec(&branch_key),
+                &branch_node_to_vec(&branch),
+            )?;
+            written += 1;
+        }
+        check_count("branches", branches, written)?;
+    }
+    writer.finish()?;
+    Ok(header)
+}
+
+fn check_count(name: &str, counted: u64, written: u64) -> Result<(), Error> {
+    if counted != written {
+        return Err(Error::Store(format!(
+            "the store changed during the export, counted {} {} but wrote {}",
+            counted, name, written
+        )));
+    }
+    Ok(())
+}
+
+/// Export the tree of a store at its last committed root, see `export_root`.
+///
+/// Falls back to the root of the root branch if no root was committed, like `root::open_tree`.
+pub fn export_store<H, S, W>(
+    store: &S,
+    writer: W,
+    with_branches: bool,
+) -> Result<ExportHeader, Error>
+where
+    H: Hasher + Default + HasherId,
+    S: Backend,
+    S::DB: GetCF<ReadOptions> + IterateCF,
+    W: Write,
+{
+    let root = match read_root(store)? {
+        Some(root) => root,
+        None => match store.branches(Some(u8::MAX))?.next() {
+            Some(branch) => {
+                let (branch_key, branch) = branch?;
+                merge::<H>(
+                    branch_key.height,
+                    &branch_key.node_key,
+                    &branch.left,
+                    &branch.right,
+                )
+                .hash::<H>()
+            }
+            None => H256::zero(),
+        },
+    };
+    export_root::<H, S, W>(store, &root, writer, with_branches)
+}
+
+/// Export a tree at its current root, see `export_root`.
+pub fn export_tree<H, V, S, W>(
+    tree: &SparseMerkleTree<H, V, S>,
+    writer: W,
+    with_branches: bool,
+) -> Result<ExportHeader, Error>
+where
+    H: HasherId,
+    S: StoreIterOps,
+    W: Write,
+{
+    export_root::<H, S, W>(tree.store(), tree.root(), writer, with_branches)
+}
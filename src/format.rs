@@ -0,0 +1,224 @@
+use rocksdb::{
+    prelude::{GetCF, IterateCF, PutCF},
+    ReadOptions,
+};
+use sparse_merkle_tree::{error::Error, BranchKey, H256};
+
+use crate::{
+    backend::Backend,
+    serde::{
+        branch_key_to_vec, branch_node_merge_value_set, branch_node_version, upgrade_branch_node,
+        MergeValueSet, BRANCH_NODE_FORMAT_VERSION,
+    },
+};
+
+/// Reserved key of the format record, stored in the branch key space of a store.
+///
+/// A store created by `open` checks the format once and writes the record along with its first root branch if the
+/// store has none. A store created by `new` neither checks nor records the format.
+pub const FORMAT_KEY: &[u8] = b"smt:format";
+
+/// The on-disk format of a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreFormat {
+    /// Format version of the branch node values, see `serde::BRANCH_NODE_FORMAT_VERSION`.
+    pub version: u8,
+    /// The `MergeValue` variants used by the branch nodes, depends on the `trie` feature of the writer.
+    pub merge_value_set: MergeValueSet,
+}
+
+impl StoreFormat {
+    /// The format written by this build.
+    pub fn current() -> Self {
+        StoreFormat {
+            version: BRANCH_NODE_FORMAT_VERSION,
+            merge_value_set: MergeValueSet::current(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let merge_value_set = match self.merge_value_set {
+            MergeValueSet::Basic => 0,
+            MergeValueSet::Trie => 1,
+        };
+        vec![self.version, merge_value_set]
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Result<Self, Error> {
+        let merge_value_set = match slice {
+            [_, 0] => MergeValueSet::Basic,
+            [_, 1] => MergeValueSet::Trie,
+            _ => return Err(Error::Store(format!("invalid format record: {:?}", slice))),
+        };
+        Ok(StoreFormat {
+            version: slice[0],
+            merge_value_set,
+        })
+    }
+}
+
+/// Returns true if `key` is the key of the root branch, the stores write the format record along with it.
+pub(crate) fn is_root_branch_key(key: &BranchKey) -> bool {
+    key.height == u8::MAX && key.node_key.is_zero()
+}
+
+/// Read the format of a store, returns `None` if the store is empty.
+///
+/// Stores written before the format record was introduced are detected by their root branch,
+/// which can only tell the `MergeValueSet` if the root branch itself contains a `ShortCut`.
+pub fn read_format<S>(store: &S) -> Result<Option<StoreFormat>, Error>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+{
+    Ok(read_store_format(store)?.map(|(format, _)| format))
+}
+
+// Read the format of a store like `read_format`, along with whether it is recorded rather than detected.
+fn read_store_format<S>(store: &S) -> Result<Option<(StoreFormat, bool)>, Error>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+{
+    let space = store.branch_space();
+    if let Some(record) = space.get(store.db(), FORMAT_KEY, store.read_options())? {
+        return StoreFormat::from_slice(&record).map(|format| Some((format, true)));
+    }
+    let root_branch_key = branch_key_to_vec(&BranchKey::new(u8::MAX, H256::zero()));
+    match space.get(store.db(), &root_branch_key, store.read_options())? {
+        Some(root_branch) => Ok(Some((
+            StoreFormat {
+                version: branch_node_version(&root_branch)
+                    .map_err(|e| Error::Store(e.to_string()))?,
+                merge_value_set: branch_node_merge_value_set(&root_branch)
+                    .map_err(|e| Error::Store(e.to_string()))?,
+            },
+            false,
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Record the current format in a store.
+pub fn write_format<S>(store: &S) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: PutCF<S::WriteOptions>,
+{
+    store.branch_space().put(
+        store.db(),
+        FORMAT_KEY,
+        &StoreFormat::current().to_vec(),
+        store.write_options(),
+    )
+}
+
+/// Check that a store is empty or was written with the current format.
+pub fn check_format<S>(store: &S) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+{
+    match read_store_format(store)? {
+        Some((format, recorded)) => check_store_format(format, recorded),
+        None => Ok(()),
+    }
+}
+
+// A detected format is `Basic` whenever the root branch has no `ShortCut`, which a `trie` build may write too,
+// so only a recorded `Basic` format is refused by a `trie` build.
+fn check_store_format(format: StoreFormat, recorded: bool) -> Result<(), Error> {
+    if format.version != BRANCH_NODE_FORMAT_VERSION {
+        return Err(Error::Store(format!(
+            "store format version {} is not supported, expected version {}, run `format::migrate` to upgrade the store",
+            format.version, BRANCH_NODE_FORMAT_VERSION
+        )));
+    }
+    match (format.merge_value_set, MergeValueSet::current()) {
+        (MergeValueSet::Trie, MergeValueSet::Basic) => Err(Error::Store(
+            "store was written with the `trie` feature, which is not enabled in this build"
+                .to_string(),
+        )),
+        (MergeValueSet::Basic, MergeValueSet::Trie) if recorded => Err(Error::Store(
+            "store was written without the `trie` feature, which is enabled in this build"
+                .to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check the format of a store when it is opened like `check_format`, returns true if the store has no
+/// format record, which the opened store then writes along with its first root branch.
+pub(crate) fn open_format<S>(store: &S) -> Result<bool, Error>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+{
+    match read_store_format(store)? {
+        Some((format, recorded)) => {
+            check_store_format(format, recorded)?;
+            Ok(!recorded)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Check the format record of a store before its root branch is written, returns the record to write if the
+/// store has none. A record of another format is an error rather than being overwritten.
+pub(crate) fn missing_format_record(record: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+    match record {
+        Some(record) => {
+            check_store_format(StoreFormat::from_slice(record)?, true)?;
+            Ok(None)
+        }
+        None => Ok(Some(StoreFormat::current().to_vec())),
+    }
+}
+
+/// Rewrite all branch nodes of a store which were written with an older format version, then record the format.
+///
+/// Branches already in the current format are skipped, so an interrupted migration can be run again.
+/// The recorded `MergeValueSet` is the one found in the branches, which may not match this build.
+/// Returns the number of rewritten branches.
+pub fn migrate<S>(store: &S) -> Result<usize, Error>
+where
+    S: Backend,
+    S::DB: IterateCF + PutCF<S::WriteOptions>,
+{
+    let space = store.branch_space();
+    let mut migrated = 0;
+    let mut merge_value_set = None;
+    for (key, value) in space.iter(store.db(), 33, store.read_options())? {
+        if branch_node_merge_value_set(&value).map_err(|e| Error::Store(e.to_string()))?
+            == MergeValueSet::Trie
+        {
+            merge_value_set = Some(MergeValueSet::Trie);
+        } else if merge_value_set.is_none() {
+            merge_value_set = Some(MergeValueSet::Basic);
+        }
+
+        let version = branch_node_version(&value).map_err(|e| Error::Store(e.to_string()))?;
+        if version == BRANCH_NODE_FORMAT_VERSION {
+            continue;
+        }
+        let value = upgrade_branch_node(&value).map_err(|e| Error::Store(e.to_string()))?;
+        space.put(
+            store.db(),
+            &key[space.prefix.len()..],
+            &value,
+            store.write_options(),
+        )?;
+        migrated += 1;
+    }
+    let format = StoreFormat {
+        version: BRANCH_NODE_FORMAT_VERSION,
+        merge_value_set: merge_value_set.unwrap_or_else(MergeValueSet::current),
+    };
+    space.put(
+        store.db(),
+        FORMAT_KEY,
+        &format.to_vec(),
+        store.write_options(),
+    )?;
+    Ok(migrated)
+}
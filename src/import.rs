@@ -0,0 +1,279 @@
+use std::io::{BufReader, Read};
+
+use rocksdb::{
+    prelude::{GetCF, IterateCF, WriteOps},
+    Direction, ReadOptions, WriteOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, Value},
+    SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::Backend,
+    export::{Crc32, ExportHeader, HasherId, EXPORT_FORMAT_VERSION},
+    format::{StoreFormat, FORMAT_KEY},
+    iter::StoreIterOps,
+    repair::{BatchWriter, BranchBuilder},
+    root::ROOT_KEY,
+    serde::{branch_key_to_vec, slice_to_branch_node, MAX_BRANCH_NODE_LEN},
+};
+
+// The initial capacity of a record value, which grows as the value is read.
+const READ_CHUNK_LEN: usize = 8 * 1024;
+
+// Reads an export and its checksum.
+struct ImportReader<R> {
+    inner: BufReader<R>,
+    checksum: Crc32,
+}
+
+impl<R: Read> ImportReader<R> {
+    fn read(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0u8; len];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| Error::Store(format!("failed to read the export: {}", e)))?;
+        self.checksum.update(&buf);
+        Ok(buf)
+    }
+
+    // Read a record whose value is at most `max_len` bytes. The length is read before the checksum is checked, so
+    // the value is read in chunks rather than into a buffer of that length, which a truncated export never fills.
+    fn read_record(&mut self, key_len: usize, max_len: usize) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let key = self.read(key_len)?;
+        let len = u32::from_be_bytes(self.read(4)?.try_into().expect("checked length")) as usize;
+        if len > max_len {
+            return Err(Error::Store(format!(
+                "invalid export record length {}, expected at most {}",
+                len, max_len
+            )));
+        }
+        let mut value = Vec::with_capacity(len.min(READ_CHUNK_LEN));
+        (&mut self.inner)
+            .take(len as u64)
+            .read_to_end(&mut value)
+            .map_err(|e| Error::Store(format!("failed to read the export: {}", e)))?;
+        if value.len() != len {
+            return Err(Error::Store(
+                "failed to read the export: unexpected end of file".to_string(),
+            ));
+        }
+        self.checksum.update(&value);
+        Ok((key, value))
+    }
+
+    // Check the checksum at the end of the export.
+    fn finish(mut self) -> Result<(), Error> {
+        let expected = self.checksum.finish();
+        let actual = u32::from_be_bytes(self.read(4)?.try_into().expect("checked length"));
+        if actual != expected {
+            return Err(Error::Store(format!(
+                "invalid export checksum, expected {:#010x} actual {:#010x}",
+                expected, actual
+            )));
+        }
+        let mut rest = [0u8; 1];
+        match self.inner.read(&mut rest) {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(Error::Store(
+                "trailing bytes after the export checksum".to_string(),
+            )),
+            Err(e) => Err(Error::Store(format!("failed to read the export: {}", e))),
+        }
+    }
+}
+
+fn check_header<H: HasherId>(header: &ExportHeader) -> Result<(), Error> {
+    if header.version != EXPORT_FORMAT_VERSION {
+        return Err(Error::Store(format!(
+            "export format version {} is not supported, expected version {}",
+            header.version, EXPORT_FORMAT_VERSION
+        )));
+    }
+    if header.hasher_id != H::HASHER_ID {
+        return Err(Error::Store(format!(
+            "export was written with hasher {}, expected hasher {}",
+            header.hasher_id,
+            H::HASHER_ID
+        )));
+    }
+    let current = StoreFormat::current();
+    if header.branches.is_some() && header.store_format.merge_value_set != current.merge_value_set {
+        return Err(Error::Store(format!(
+            "export branches were written with {:?} merge values, this build writes {:?}",
+            header.store_format.merge_value_set, current.merge_value_set
+        )));
+    }
+    Ok(())
+}
+
+/// Import an export of `export::export_store` into an empty store, returns the header of the export.
+///
+/// The leaves are written as they are read, then the branches are computed from the leaves, like
+/// `repair::rebuild_branches`, and the exported branches, if any, are checked against them. The import fails if
+/// the computed root is not the root of the header, or if the export is truncated or its checksum is invalid.
+/// A failed import deletes the records it wrote, the other records of the key spaces of the store are kept.
+/// The root is recorded last, so an interrupted import leaves no root, see `drop::drop_tree`.
+/// The keys and the hashes of the leaves are sorted in memory.
+pub fn import_store<H, V, S, R>(store: &S, reader: R) -> Result<ExportHeader, Error>
+where
+    H: Hasher + Default + HasherId,
+    V: Value + From<Box<[u8]>>,
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+    R: Read,
+{
+    if store
+        .leaves::<Box<[u8]>>(None, Direction::Forward)?
+        .next()
+        .is_some()
+        || store.branches(None)?.next().is_some()
+    {
+        return Err(Error::Store(
+            "the store of an import must be empty".to_string(),
+        ));
+    }
+    let mut reader = ImportReader {
+        inner: BufReader::new(reader),
+        checksum: Crc32::new(),
+    };
+    let header = ExportHeader::from_slice(&reader.read(ExportHeader::LEN)?)?;
+    check_header::<H>(&header)?;
+
+    let format_missing = store
+        .branch_space()
+        .get(store.db(), FORMAT_KEY, store.read_options())?
+        .is_none();
+    let mut leaves = Vec::new();
+    let result = import_records::<H, V, S, R>(store, &header, reader, &mut leaves);
+    if result.is_err() {
+        delete_imported::<H, S>(store, leaves, format_missing)?;
+    }
+    result.map(|_| header)
+}
+
+// Delete the records written by a failed import, the leaves, the branches computed from them, which are computed
+// again, and the format record if the store had none. The other records of the key spaces of the store are kept.
+fn delete_imported<H, S>(
+    store: &S,
+    mut leaves: Vec<(H256, H256)>,
+    format_missing: bool,
+) -> Result<(), Error>
+where
+    H: Hasher + Default,
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + WriteOps,
+{
+    leaves.sort_unstable_by_key(|(key, _)| *key);
+    leaves.dedup_by_key(|(key, _)| *key);
+    let (branch_space, leaf_space) = (store.branch_space(), store.leaf_space());
+    let mut writer = BatchWriter::new(store);
+    for (key, _) in leaves.iter() {
+        leaf_space.batch_delete(&mut writer.batch, key.as_slice())?;
+        writer.added()?;
+    }
+    let mut builder = BranchBuilder::<H, _>::new(|branch_key, _| {
+        branch_space.batch_delete(&mut writer.batch, &branch_key_to_vec(&branch_key))?;
+        writer.added()
+    });
+    for (key, hash) in leaves {
+        builder.push(key, hash)?;
+    }
+    builder.finish()?;
+    if format_missing {
+        branch_space.batch_delete(&mut writer.batch, FORMAT_KEY)?;
+    }
+    writer.flush()
+}
+
+fn import_records<H, V, S, R>(
+    store: &S,
+    header: &ExportHeader,
+    mut reader: ImportReader<R>,
+    leaves: &mut Vec<(H256, H256)>,
+) -> Result<(), Error>
+where
+    H: Hasher + Default,
+    V: Value + From<Box<[u8]>>,
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + WriteOps,
+    R: Read,
+{
+    let mut writer = BatchWriter::new(store);
+    let leaf_space = store.leaf_space();
+    for _ in 0..header.leaves {
+        let (key, value) = reader.read_record(32, u32::MAX as usize)?;
+        let key: [u8; 32] = key.try_into().expect("checked leaf key length");
+        let hash = V::from(value.clone().into_boxed_slice()).to_h256();
+        if hash.is_zero() {
+            continue;
+        }
+        leaf_space.batch_put(&mut writer.batch, &key, &value)?;
+        writer.added()?;
+        leaves.push((H256::from(key), hash));
+    }
+    leaves.sort_unstable_by_key(|(key, _)| *key);
+
+    let mut branches = 0;
+    let mut builder = BranchBuilder::<H, _>::new(|branch_key, branch| {
+        branches += 1;
+        writer.write_branch(branch_key, branch)
+    });
+    for (key, hash) in leaves.iter() {
+        builder.push(*key, *hash)?;
+    }
+    let root = builder.finish()?;
+    writer.flush()?;
+    if root != header.root {
+        return Err(Error::Store(format!(
+            "the root of the imported leaves {:?} is not the root of the export {:?}",
+            root, header.root
+        )));
+    }
+
+    if let Some(exported) = header.branches {
+        if exported != branches {
+            return Err(Error::Store(format!(
+                "the export has {} branches, the imported leaves have {}",
+                exported, branches
+            )));
+        }
+        let branch_space = store.branch_space();
+        for _ in 0..exported {
+            let (key, value) = reader.read_record(33, MAX_BRANCH_NODE_LEN)?;
+            let branch = slice_to_branch_node(&value).map_err(|e| Error::Store(e.to_string()))?;
+            let stored = branch_space
+                .get(store.db(), &key, store.read_options())?
+                .map(|stored| slice_to_branch_node(&stored))
+                .transpose()
+                .map_err(|e| Error::Store(e.to_string()))?;
+            if stored.as_ref() != Some(&branch) {
+                return Err(Error::Store(format!(
+                    "the exported branch {:?} does not match the imported leaves",
+                    key
+                )));
+            }
+        }
+    }
+    reader.finish()?;
+
+    store
+        .branch_space()
+        .batch_put(&mut writer.batch, ROOT_KEY, root.as_slice())?;
+    writer.flush()
+}
+
+/// Import an export into an empty store, returns the tree at the root of the export, see `import_store`.
+pub fn import_tree<H, V, S, R>(store: S, reader: R) -> Result<SparseMerkleTree<H, V, S>, Error>
+where
+    H: Hasher + Default + HasherId,
+    V: Value + From<Box<[u8]>>,
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+    R: Read,
+{
+    let header = import_store::<H, V, S, R>(&store, reader)?;
+    Ok(SparseMerkleTree::new(header.root, store))
+}
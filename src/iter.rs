@@ -0,0 +1,123 @@
+use std::marker::PhantomData;
+
+use rocksdb::{prelude::IterateCF, Direction};
+use sparse_merkle_tree::{error::Error, BranchKey, BranchNode, H256};
+
+use crate::{
+    backend::{Backend, KeyValue},
+    serde::slice_to_branch_node,
+};
+
+/// An iterator over the leaves of a store, see `StoreIterOps::leaves`.
+pub struct Leaves<'a, V> {
+    inner: Box<dyn Iterator<Item = KeyValue> + 'a>,
+    // The length of the prefix of the leaf keys.
+    prefix_len: usize,
+    phantom: PhantomData<fn() -> V>,
+}
+
+impl<'a, V: From<Box<[u8]>>> Iterator for Leaves<'a, V> {
+    type Item = (H256, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        let key: [u8; 32] = key[self.prefix_len..]
+            .try_into()
+            .expect("checked leaf key length");
+        Some((key.into(), value.into()))
+    }
+}
+
+/// An iterator over the branches of a store, see `StoreIterOps::branches`.
+pub struct Branches<'a> {
+    inner: Box<dyn Iterator<Item = KeyValue> + 'a>,
+    // The length of the prefix of the branch keys.
+    prefix_len: usize,
+}
+
+impl<'a> Branches<'a> {
+    /// Iterate over the keys of the branches with the decoded branches, so the key of a branch which can not be
+    /// decoded is returned with the error.
+    pub fn with_keys(self) -> impl Iterator<Item = (BranchKey, Result<BranchNode, Error>)> + 'a {
+        let prefix_len = self.prefix_len;
+        self.inner
+            .map(move |(key, value)| decode_branch(&key[prefix_len..], &value))
+    }
+}
+
+impl<'a> Iterator for Branches<'a> {
+    type Item = Result<(BranchKey, BranchNode), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        let (branch_key, branch) = decode_branch(&key[self.prefix_len..], &value);
+        Some(branch.map(|branch| (branch_key, branch)))
+    }
+}
+
+// Decode a branch record, whose key is without the prefix of the store.
+fn decode_branch(key: &[u8], value: &[u8]) -> (BranchKey, Result<BranchNode, Error>) {
+    let node_key: [u8; 32] = key[1..].try_into().expect("checked branch key length");
+    let branch_key = BranchKey::new(key[0], node_key.into());
+    let branch = slice_to_branch_node(value).map_err(|e| Error::Store(e.to_string()));
+    (branch_key, branch)
+}
+
+/// Iteration over the records of a SMT store, implemented for every store type.
+///
+/// The iterators read from an implicit snapshot taken when they are created, or from the snapshot of the store.
+pub trait StoreIterOps {
+    /// Iterate over the leaves of the tree in the byte order of their keys, which is not the order of `H256::cmp`,
+    /// in the given direction, starting from the first leaf at or after `from` (at or before `from` in reverse),
+    /// or from the first (last in reverse) leaf.
+    fn leaves<V>(&self, from: Option<&H256>, direction: Direction) -> Result<Leaves<'_, V>, Error>;
+
+    /// Iterate over the decoded branches of the tree ordered by height, then by the bytes of their node keys,
+    /// only the branches at `height` if given. A branch which can not be decoded is returned as an error.
+    fn branches(&self, height: Option<u8>) -> Result<Branches<'_>, Error>;
+}
+
+impl<S> StoreIterOps for S
+where
+    S: Backend,
+    S::DB: IterateCF,
+{
+    fn leaves<V>(&self, from: Option<&H256>, direction: Direction) -> Result<Leaves<'_, V>, Error> {
+        let space = self.leaf_space();
+        let prefix_len = space.prefix.len();
+        let iter = space
+            .scan_from(
+                self.db(),
+                from.map(H256::as_slice),
+                direction,
+                self.read_options(),
+            )?
+            // skip the branches and the metadata records
+            .filter(move |(key, _)| key.len() == prefix_len + 32);
+        Ok(Leaves {
+            inner: Box::new(iter),
+            prefix_len,
+            phantom: PhantomData,
+        })
+    }
+
+    fn branches(&self, height: Option<u8>) -> Result<Branches<'_>, Error> {
+        let space = self.branch_space();
+        let prefix_len = space.prefix.len();
+        let from = height.map(|height| [height]);
+        let iter = space
+            .scan_from(
+                self.db(),
+                from.as_ref().map(|from| &from[..]),
+                Direction::Forward,
+                self.read_options(),
+            )?
+            .take_while(move |(key, _)| height.is_none() || key.get(prefix_len) == height.as_ref())
+            // a branch key is a height byte followed by a node key, unlike the leaves and the metadata records
+            .filter(move |(key, _)| key.len() == prefix_len + 33);
+        Ok(Branches {
+            inner: Box::new(iter),
+            prefix_len,
+        })
+    }
+}
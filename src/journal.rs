@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+
+use rocksdb::{
+    prelude::{GetCF, IterateCF, WriteOps},
+    Direction, ReadOptions, WriteBatch, WriteOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::{prefix_end, Backend, KeyValue},
+    batch_store::WriteBatchStore,
+    root::{open_tree_with, read_root, ROOT_KEY},
+    serde::{branch_key_to_vec, branch_node_to_vec},
+};
+
+/// Reserved prefix of the journal records, stored in the branch key space of a store and followed by
+/// the big-endian sequence number of the commit.
+pub const JOURNAL_PREFIX: &[u8] = b"smt:journal:";
+
+const BRANCH_CHANGE: u8 = 0;
+const LEAF_CHANGE: u8 = 1;
+
+/// A branch or a leaf of the tree changed by a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalNode {
+    Branch(BranchKey),
+    Leaf(H256),
+}
+
+/// The stored value of a node before and after a commit, `None` if the node was not stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub node: JournalNode,
+    pub old: Option<Vec<u8>>,
+    pub new: Option<Vec<u8>>,
+}
+
+/// The journal record of a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    /// The committed root before the commit, zero if no root was committed.
+    pub old_root: H256,
+    pub new_root: H256,
+    pub changes: Vec<Change>,
+}
+
+impl JournalEntry {
+    fn to_vec(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        ret.extend_from_slice(self.old_root.as_slice());
+        ret.extend_from_slice(self.new_root.as_slice());
+        for change in &self.changes {
+            match &change.node {
+                JournalNode::Branch(key) => {
+                    ret.push(BRANCH_CHANGE);
+                    ret.extend_from_slice(&branch_key_to_vec(key));
+                }
+                JournalNode::Leaf(key) => {
+                    ret.push(LEAF_CHANGE);
+                    ret.extend_from_slice(key.as_slice());
+                }
+            }
+            for value in [&change.old, &change.new] {
+                match value {
+                    Some(value) => {
+                        ret.push(1);
+                        ret.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                        ret.extend_from_slice(value);
+                    }
+                    None => ret.push(0),
+                }
+            }
+        }
+        ret
+    }
+
+    fn from_slice(sequence: u64, mut slice: &[u8]) -> Result<Self, Error> {
+        let old_root = take_h256(&mut slice)?;
+        let new_root = take_h256(&mut slice)?;
+        let mut changes = Vec::new();
+        while !slice.is_empty() {
+            let node = match take(&mut slice, 1)?[0] {
+                BRANCH_CHANGE => {
+                    let height = take(&mut slice, 1)?[0];
+                    JournalNode::Branch(BranchKey::new(height, take_h256(&mut slice)?))
+                }
+                LEAF_CHANGE => JournalNode::Leaf(take_h256(&mut slice)?),
+                tag => {
+                    return Err(Error::Store(format!(
+                        "invalid journal entry {}: node tag {}",
+                        sequence, tag
+                    )))
+                }
+            };
+            let old = take_value(&mut slice)?;
+            let new = take_value(&mut slice)?;
+            changes.push(Change { node, old, new });
+        }
+        Ok(JournalEntry {
+            sequence,
+            old_root,
+            new_root,
+            changes,
+        })
+    }
+}
+
+// Split the first `len` bytes off `slice`.
+fn take<'a>(slice: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if slice.len() < len {
+        return Err(Error::Store("invalid journal entry: truncated".to_string()));
+    }
+    let (head, tail) = slice.split_at(len);
+    *slice = tail;
+    Ok(head)
+}
+
+fn take_h256(slice: &mut &[u8]) -> Result<H256, Error> {
+    let bytes: [u8; 32] = take(slice, 32)?
+        .try_into()
+        .map_err(|_| Error::Store("invalid journal entry: truncated".to_string()))?;
+    Ok(bytes.into())
+}
+
+fn take_value(slice: &mut &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    match take(slice, 1)?[0] {
+        0 => Ok(None),
+        1 => {
+            let len: [u8; 4] = take(slice, 4)?
+                .try_into()
+                .map_err(|_| Error::Store("invalid journal entry: truncated".to_string()))?;
+            Ok(Some(
+                take(slice, u32::from_be_bytes(len) as usize)?.to_vec(),
+            ))
+        }
+        flag => Err(Error::Store(format!(
+            "invalid journal entry: value flag {}",
+            flag
+        ))),
+    }
+}
+
+fn journal_key(sequence: u64) -> Vec<u8> {
+    [JOURNAL_PREFIX, &sequence.to_be_bytes()].concat()
+}
+
+// Iterate over the journal records of a store in the given direction.
+fn journal_records<'b, S>(
+    store: &'b S,
+    direction: Direction,
+) -> Result<impl Iterator<Item = Result<JournalEntry, Error>> + 'b, Error>
+where
+    S: Backend,
+    S::DB: IterateCF,
+{
+    let space = store.branch_space();
+    let from = match direction {
+        Direction::Forward => JOURNAL_PREFIX.to_vec(),
+        Direction::Reverse => prefix_end(JOURNAL_PREFIX).expect("the prefix is not all 0xff"),
+    };
+    let reverse = matches!(direction, Direction::Reverse);
+    let end = space.key(&from);
+    let prefix = space.key(JOURNAL_PREFIX);
+    let key_len = prefix.len() + 8;
+    Ok(space
+        .scan_from(store.db(), Some(&from), direction, store.read_options())?
+        // in reverse, the iteration can start at the end of the prefix
+        .skip_while(move |(key, _)| reverse && key.as_ref() >= end.as_slice())
+        .take_while(move |(key, _)| key.starts_with(&prefix))
+        .filter(move |(key, _)| key.len() == key_len)
+        .map(move |(key, value): KeyValue| {
+            let sequence: [u8; 8] = key[key_len - 8..]
+                .try_into()
+                .map_err(|_| Error::Store(format!("invalid journal key: {:?}", key)))?;
+            JournalEntry::from_slice(u64::from_be_bytes(sequence), &value)
+        }))
+}
+
+/// Read the journal of a store, oldest commit first.
+pub fn read_journal<S>(store: &S) -> Result<Vec<JournalEntry>, Error>
+where
+    S: Backend,
+    S::DB: IterateCF,
+{
+    journal_records(store, Direction::Forward)?.collect()
+}
+
+/// A SMT `Store` which buffers the writes of another store like a `batch_store::WriteBatchStore`, and records
+/// the old and new values of the changed branches and leaves in a journal entry written atomically by `commit`,
+/// so the commit can later be undone with `rollback` / `rollback_to`.
+///
+/// Commits of the tree made without the journal, e.g. with `root::commit_root`, can not be rolled back.
+pub struct JournalStore<S, V> {
+    inner: WriteBatchStore<S, V>,
+    // The stored values of the branches and leaves written since the last commit, before their first write.
+    old_branches: HashMap<BranchKey, Option<Vec<u8>>>,
+    old_leaves: HashMap<H256, Option<Vec<u8>>>,
+}
+
+impl<S: Backend, V> JournalStore<S, V> {
+    pub fn new(inner: S) -> Self {
+        JournalStore {
+            inner: WriteBatchStore::new(inner),
+            old_branches: HashMap::new(),
+            old_leaves: HashMap::new(),
+        }
+    }
+}
+
+impl<S, V> JournalStore<S, V> {
+    /// The wrapped store, which does not see the pending writes.
+    pub fn inner(&self) -> &S {
+        self.inner.inner()
+    }
+
+    /// Returns the number of pending writes in the batch.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Drop all pending writes.
+    pub fn discard(&mut self) {
+        self.inner.discard();
+        self.old_branches.clear();
+        self.old_leaves.clear();
+    }
+
+    /// Atomically apply the pending writes, record `root` as the committed root and append the journal entry
+    /// of the commit.
+    pub fn commit(&mut self, root: &H256) -> Result<(), Error>
+    where
+        S: Backend<WriteOptions = WriteOptions> + StoreReadOps<V>,
+        S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+        V: Clone + AsRef<[u8]>,
+    {
+        let mut changes = Vec::with_capacity(self.old_branches.len() + self.old_leaves.len());
+        for (key, old) in &self.old_branches {
+            changes.push(Change {
+                node: JournalNode::Branch(key.clone()),
+                old: old.clone(),
+                new: self
+                    .inner
+                    .get_branch(key)?
+                    .map(|branch| branch_node_to_vec(&branch)),
+            });
+        }
+        for (key, old) in &self.old_leaves {
+            changes.push(Change {
+                node: JournalNode::Leaf(*key),
+                old: old.clone(),
+                new: self.inner.get_leaf(key)?.map(|leaf| leaf.as_ref().to_vec()),
+            });
+        }
+
+        let (store, batch) = self.inner.inner_and_batch();
+        let sequence = match journal_records(store, Direction::Reverse)?.next() {
+            Some(last) => last?.sequence + 1,
+            None => 0,
+        };
+        let entry = JournalEntry {
+            sequence,
+            old_root: read_root(store)?.unwrap_or_else(H256::zero),
+            new_root: *root,
+            changes,
+        };
+        store
+            .branch_space()
+            .batch_put(batch, &journal_key(sequence), &entry.to_vec())?;
+        self.inner.commit(root)?;
+        self.old_branches.clear();
+        self.old_leaves.clear();
+        Ok(())
+    }
+}
+
+impl<S, V> StoreReadOps<V> for JournalStore<S, V>
+where
+    S: StoreReadOps<V>,
+    V: Clone,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        self.inner.get_branch(branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        self.inner.get_leaf(leaf_key)
+    }
+}
+
+impl<S, V> JournalStore<S, V>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+{
+    // Record the stored value of a branch before its first write since the last commit.
+    fn record_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        if !self.old_branches.contains_key(node_key) {
+            let store = self.inner.inner();
+            let old = store.branch_space().get(
+                store.db(),
+                &branch_key_to_vec(node_key),
+                store.read_options(),
+            )?;
+            self.old_branches
+                .insert(node_key.clone(), old.map(|old| old.to_vec()));
+        }
+        Ok(())
+    }
+
+    // Record the stored value of a leaf before its first write since the last commit.
+    fn record_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        if !self.old_leaves.contains_key(leaf_key) {
+            let store = self.inner.inner();
+            let old =
+                store
+                    .leaf_space()
+                    .get(store.db(), leaf_key.as_slice(), store.read_options())?;
+            self.old_leaves
+                .insert(*leaf_key, old.map(|old| old.to_vec()));
+        }
+        Ok(())
+    }
+}
+
+impl<S, V> StoreWriteOps<V> for JournalStore<S, V>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+    V: Value + AsRef<[u8]>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        self.record_branch(&node_key)?;
+        self.inner.insert_branch(node_key, branch)
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.record_leaf(&leaf_key)?;
+        self.inner.insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        self.record_branch(node_key)?;
+        self.inner.remove_branch(node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.record_leaf(leaf_key)?;
+        self.inner.remove_leaf(leaf_key)
+    }
+}
+
+/// Open a tree at the last committed root of `store`, journaling its updates in a `JournalStore`.
+pub fn open_tree<H, V, S>(store: S) -> Result<SparseMerkleTree<H, V, JournalStore<S, V>>, Error>
+where
+    H: Hasher + Default,
+    V: Value + Clone,
+    S: Backend + StoreReadOps<V>,
+    S::DB: GetCF<ReadOptions>,
+{
+    open_tree_with(store, JournalStore::new)
+}
+
+/// Atomically apply the pending updates of a tree along with its current root and its journal entry.
+pub fn commit_tree<H, V, S>(
+    tree: &mut SparseMerkleTree<H, V, JournalStore<S, V>>,
+) -> Result<(), Error>
+where
+    S: Backend<WriteOptions = WriteOptions> + StoreReadOps<V>,
+    S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+    V: Clone + AsRef<[u8]>,
+{
+    let root = *tree.root();
+    tree.store_mut().commit(&root)
+}
+
+// Restore the old values of the journal `entries`, newest first, and their oldest root, then delete the entries,
+// in one write batch. Returns the restored root.
+fn undo<S>(store: &S, entries: &[JournalEntry]) -> Result<H256, Error>
+where
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + WriteOps,
+{
+    let root = read_root(store)?.unwrap_or_else(H256::zero);
+    let (newest, oldest) = match (entries.first(), entries.last()) {
+        (Some(newest), Some(oldest)) => (newest, oldest),
+        _ => return Ok(root),
+    };
+    if newest.new_root != root {
+        return Err(Error::Store(format!(
+            "the committed root {:?} is not the root of the last journal entry {:?}, \
+             the tree was committed without the journal",
+            root, newest.new_root
+        )));
+    }
+
+    let branch_space = store.branch_space();
+    let leaf_space = store.leaf_space();
+    let mut batch = WriteBatch::default();
+    // the values of the older entries are written last, so they win
+    for entry in entries {
+        for change in &entry.changes {
+            let (space, key) = match &change.node {
+                JournalNode::Branch(key) => (branch_space, branch_key_to_vec(key)),
+                JournalNode::Leaf(key) => (leaf_space, key.as_slice().to_vec()),
+            };
+            match &change.old {
+                Some(old) => space.batch_put(&mut batch, &key, old)?,
+                None => space.batch_delete(&mut batch, &key)?,
+            }
+        }
+        branch_space.batch_delete(&mut batch, &journal_key(entry.sequence))?;
+    }
+    branch_space.batch_put(&mut batch, ROOT_KEY, oldest.old_root.as_slice())?;
+    store
+        .db()
+        .write_full(&batch, store.write_options())
+        .map_err(|e| Error::Store(e.to_string()))?;
+    Ok(oldest.old_root)
+}
+
+/// Roll the tree of a store back by its last `commits` journaled commits, returns the restored root.
+///
+/// The changes and the root are restored atomically and the rolled back entries are removed from the journal.
+/// Returns an error if the journal has fewer entries, or if the last commit was not journaled.
+/// Reopen the tree afterwards, e.g. with `open_tree`.
+pub fn rollback<S>(store: &S, commits: usize) -> Result<H256, Error>
+where
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+{
+    let entries = journal_records(store, Direction::Reverse)?
+        .take(commits)
+        .collect::<Result<Vec<_>, Error>>()?;
+    if entries.len() < commits {
+        return Err(Error::Store(format!(
+            "can not roll back {} commits, the journal has {} entries",
+            commits,
+            entries.len()
+        )));
+    }
+    undo(store, &entries)
+}
+
+/// Roll the tree of a store back to `root`, the committed root before one of its journaled commits,
+/// returns the number of rolled back commits.
+///
+/// Rolls back to the latest commit with this root, and does nothing if `root` is the committed root.
+/// Returns an error if `root` is not in the journal.
+pub fn rollback_to<S>(store: &S, root: &H256) -> Result<usize, Error>
+where
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+{
+    if read_root(store)?.unwrap_or_else(H256::zero) == *root {
+        return Ok(0);
+    }
+    let mut entries = Vec::new();
+    for entry in journal_records(store, Direction::Reverse)? {
+        let entry = entry?;
+        let found = entry.old_root == *root;
+        entries.push(entry);
+        if found {
+            undo(store, &entries)?;
+            return Ok(entries.len());
+        }
+    }
+    Err(Error::Store(format!(
+        "root {:?} is not in the journal",
+        root
+    )))
+}
+
+/// Delete the oldest journal entries of a store, keeping the last `keep`, returns the number of deleted entries.
+///
+/// The deleted commits can no longer be rolled back.
+pub fn truncate_journal<S>(store: &S, keep: usize) -> Result<usize, Error>
+where
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: IterateCF + WriteOps,
+{
+    let space = store.branch_space();
+    let mut batch = WriteBatch::default();
+    let mut deleted = 0;
+    for entry in journal_records(store, Direction::Reverse)?.skip(keep) {
+        space.batch_delete(&mut batch, &journal_key(entry?.sequence))?;
+        deleted += 1;
+    }
+    store
+        .db()
+        .write_full(&batch, store.write_options())
+        .map_err(|e| Error::Store(e.to_string()))?;
+    Ok(deleted)
+}
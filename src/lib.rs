@@ -1,5 +1,26 @@
+pub mod backend;
+pub mod batch_read;
+pub mod batch_store;
+pub mod bulk_load;
+pub mod cached_store;
 pub mod cf_store;
 pub mod default_store;
+pub mod drop;
+pub mod export;
+pub mod format;
+pub mod import;
+pub mod iter;
+pub mod journal;
+pub mod multi_get;
+pub mod namespace;
+pub mod overlay_store;
+pub mod prune;
+pub mod range;
+pub mod registry;
+pub mod repair;
+pub mod root;
 pub mod serde;
 #[cfg(test)]
 mod tests;
+pub mod verify;
+pub mod versioned;
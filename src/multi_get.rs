@@ -0,0 +1,232 @@
+use std::os::raw::c_char;
+
+use rocksdb::{
+    ffi, ffi_util::error_message, ColumnFamily, DBVector, DBWithTTL, Handle, OptimisticTransaction,
+    OptimisticTransactionDB, ReadOnlyDB, ReadOptions, SecondaryDB, Transaction, TransactionDB, DB,
+};
+use sparse_merkle_tree::error::Error;
+
+/// Read several keys with one RocksDB `multi_get`, which the RocksDB binding does not wrap.
+///
+/// Implemented for the databases and the transactions. A snapshot does not expose its database, so a store
+/// reading a snapshot in batches should read the database with `ReadOptions::set_snapshot` instead.
+pub trait MultiGetCF {
+    /// Read `keys` of the column family `cf`, or of the default column family if `None`,
+    /// returns the values in the order of `keys`.
+    fn multi_get_cf_full<K: AsRef<[u8]>>(
+        &self,
+        cf: Option<&ColumnFamily>,
+        keys: &[K],
+        read_options: Option<&ReadOptions>,
+    ) -> Result<Vec<Option<DBVector>>, Error>;
+}
+
+// The arguments of a `multi_get` of the C API: the read options, the keys and their lengths,
+// and the values, the value lengths and the errors filled by the call.
+struct MultiGetArgs {
+    read_options: *const ffi::rocksdb_readoptions_t,
+    cfs: Option<Vec<*const ffi::rocksdb_column_family_handle_t>>,
+    keys: Vec<*const c_char>,
+    key_lens: Vec<usize>,
+    values: Vec<*mut c_char>,
+    value_lens: Vec<usize>,
+    errs: Vec<*mut c_char>,
+}
+
+// Call a `multi_get` of the C API with `keys`, then take the values it allocated.
+fn multi_get<K, F>(
+    cf: Option<&ColumnFamily>,
+    keys: &[K],
+    read_options: Option<&ReadOptions>,
+    call: F,
+) -> Result<Vec<Option<DBVector>>, Error>
+where
+    K: AsRef<[u8]>,
+    F: FnOnce(&mut MultiGetArgs),
+{
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    let default_read_options = ReadOptions::default();
+    let read_options = read_options.unwrap_or(&default_read_options);
+    let mut args = MultiGetArgs {
+        read_options: read_options.handle(),
+        cfs: cf.map(|cf| vec![cf.handle() as *const _; keys.len()]),
+        keys: keys
+            .iter()
+            .map(|key| key.as_ref().as_ptr() as *const c_char)
+            .collect(),
+        key_lens: keys.iter().map(|key| key.as_ref().len()).collect(),
+        values: vec![std::ptr::null_mut(); keys.len()],
+        value_lens: vec![0; keys.len()],
+        errs: vec![std::ptr::null_mut(); keys.len()],
+    };
+    call(&mut args);
+
+    // every value and error is allocated by RocksDB, so all of them are taken even if a key failed
+    let mut err = None;
+    let values = args
+        .values
+        .into_iter()
+        .zip(args.value_lens)
+        .zip(args.errs)
+        .map(|((value, len), e)| {
+            if !e.is_null() {
+                let e = error_message(e);
+                err.get_or_insert(e);
+            }
+            (!value.is_null()).then(|| unsafe { DBVector::from_c(value as *mut u8, len) })
+        })
+        .collect();
+    match err {
+        Some(e) => Err(Error::Store(e)),
+        None => Ok(values),
+    }
+}
+
+fn db_multi_get<T, K>(
+    db: &T,
+    cf: Option<&ColumnFamily>,
+    keys: &[K],
+    read_options: Option<&ReadOptions>,
+) -> Result<Vec<Option<DBVector>>, Error>
+where
+    T: Handle<ffi::rocksdb_t>,
+    K: AsRef<[u8]>,
+{
+    multi_get(cf, keys, read_options, |args| unsafe {
+        match &args.cfs {
+            Some(cfs) => ffi::rocksdb_multi_get_cf(
+                db.handle(),
+                args.read_options,
+                cfs.as_ptr(),
+                args.keys.len(),
+                args.keys.as_ptr(),
+                args.key_lens.as_ptr(),
+                args.values.as_mut_ptr(),
+                args.value_lens.as_mut_ptr(),
+                args.errs.as_mut_ptr(),
+            ),
+            None => ffi::rocksdb_multi_get(
+                db.handle(),
+                args.read_options,
+                args.keys.len(),
+                args.keys.as_ptr(),
+                args.key_lens.as_ptr(),
+                args.values.as_mut_ptr(),
+                args.value_lens.as_mut_ptr(),
+                args.errs.as_mut_ptr(),
+            ),
+        }
+    })
+}
+
+fn transaction_multi_get<T, K>(
+    transaction: &T,
+    cf: Option<&ColumnFamily>,
+    keys: &[K],
+    read_options: Option<&ReadOptions>,
+) -> Result<Vec<Option<DBVector>>, Error>
+where
+    T: Handle<ffi::rocksdb_transaction_t>,
+    K: AsRef<[u8]>,
+{
+    multi_get(cf, keys, read_options, |args| unsafe {
+        match &args.cfs {
+            Some(cfs) => ffi::rocksdb_transaction_multi_get_cf(
+                transaction.handle(),
+                args.read_options,
+                cfs.as_ptr(),
+                args.keys.len(),
+                args.keys.as_ptr(),
+                args.key_lens.as_ptr(),
+                args.values.as_mut_ptr(),
+                args.value_lens.as_mut_ptr(),
+                args.errs.as_mut_ptr(),
+            ),
+            None => ffi::rocksdb_transaction_multi_get(
+                transaction.handle(),
+                args.read_options,
+                args.keys.len(),
+                args.keys.as_ptr(),
+                args.key_lens.as_ptr(),
+                args.values.as_mut_ptr(),
+                args.value_lens.as_mut_ptr(),
+                args.errs.as_mut_ptr(),
+            ),
+        }
+    })
+}
+
+macro_rules! impl_multi_get {
+    ($multi_get:ident, $($db:ty),*) => {
+        $(
+            impl MultiGetCF for $db {
+                fn multi_get_cf_full<K: AsRef<[u8]>>(
+                    &self,
+                    cf: Option<&ColumnFamily>,
+                    keys: &[K],
+                    read_options: Option<&ReadOptions>,
+                ) -> Result<Vec<Option<DBVector>>, Error> {
+                    $multi_get(self, cf, keys, read_options)
+                }
+            }
+        )*
+    };
+}
+
+impl_multi_get!(
+    db_multi_get,
+    DB,
+    DBWithTTL,
+    OptimisticTransactionDB,
+    ReadOnlyDB,
+    SecondaryDB
+);
+impl_multi_get!(transaction_multi_get, OptimisticTransaction);
+
+impl<'a, T> MultiGetCF for Transaction<'a, T> {
+    fn multi_get_cf_full<K: AsRef<[u8]>>(
+        &self,
+        cf: Option<&ColumnFamily>,
+        keys: &[K],
+        read_options: Option<&ReadOptions>,
+    ) -> Result<Vec<Option<DBVector>>, Error> {
+        transaction_multi_get(self, cf, keys, read_options)
+    }
+}
+
+impl MultiGetCF for TransactionDB {
+    fn multi_get_cf_full<K: AsRef<[u8]>>(
+        &self,
+        cf: Option<&ColumnFamily>,
+        keys: &[K],
+        read_options: Option<&ReadOptions>,
+    ) -> Result<Vec<Option<DBVector>>, Error> {
+        multi_get(cf, keys, read_options, |args| unsafe {
+            match &args.cfs {
+                Some(cfs) => ffi::rocksdb_transactiondb_multi_get_cf(
+                    self.handle(),
+                    args.read_options,
+                    cfs.as_ptr(),
+                    args.keys.len(),
+                    args.keys.as_ptr(),
+                    args.key_lens.as_ptr(),
+                    args.values.as_mut_ptr(),
+                    args.value_lens.as_mut_ptr(),
+                    args.errs.as_mut_ptr(),
+                ),
+                None => ffi::rocksdb_transactiondb_multi_get(
+                    self.handle(),
+                    args.read_options,
+                    args.keys.len(),
+                    args.keys.as_ptr(),
+                    args.key_lens.as_ptr(),
+                    args.values.as_mut_ptr(),
+                    args.value_lens.as_mut_ptr(),
+                    args.errs.as_mut_ptr(),
+                ),
+            }
+        })
+    }
+}
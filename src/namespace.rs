@@ -0,0 +1,64 @@
+use sparse_merkle_tree::error::Error;
+
+/// The key prefix of a tree in a multi-tree store, made of the length of the tree name followed by the name.
+///
+/// Unlike raw prefixes such as `tree` and `tree1`, or a prefix ending like the height byte of a branch key,
+/// the keys of two trees with different names can never overlap.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Namespace(Vec<u8>);
+
+impl Namespace {
+    /// The maximum length of a tree name.
+    pub const MAX_NAME_LEN: usize = u8::MAX as usize;
+
+    pub fn new(name: &[u8]) -> Result<Self, Error> {
+        if name.len() > Self::MAX_NAME_LEN {
+            return Err(Error::Store(format!(
+                "tree name is {} bytes, longer than {} bytes",
+                name.len(),
+                Self::MAX_NAME_LEN
+            )));
+        }
+        Ok(Namespace([&[name.len() as u8], name].concat()))
+    }
+
+    /// Decode a namespace from its key prefix.
+    pub fn from_prefix(prefix: &[u8]) -> Result<Self, Error> {
+        match prefix.split_first() {
+            Some((&len, name)) if len as usize == name.len() => Ok(Namespace(prefix.to_vec())),
+            _ => Err(Error::Store(format!("invalid namespace: {:?}", prefix))),
+        }
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.0[1..]
+    }
+
+    /// Returns the key prefix of the tree.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Namespace {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+/// Check that the keys of trees stored with `prefixes` in the same column family can not overlap,
+/// which is the case unless a prefix is a prefix of another one, e.g. `tree` and `tree1`.
+pub fn check_prefixes<P: AsRef<[u8]>>(prefixes: &[P]) -> Result<(), Error> {
+    let mut prefixes: Vec<&[u8]> = prefixes.iter().map(AsRef::as_ref).collect();
+    prefixes.sort_unstable();
+    // a prefix of another prefix sorts right before it or before another prefix which starts with it
+    for pair in prefixes.windows(2) {
+        if pair[1].starts_with(pair[0]) {
+            return Err(Error::Store(format!(
+                "ambiguous tree prefixes {:?} and {:?}, the keys of the trees can overlap",
+                pair[0], pair[1]
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use rocksdb::{prelude::GetCF, ReadOptions};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{backend::Backend, root::open_tree_with};
+
+/// A SMT `Store` which keeps the writes to another store in memory, e.g. to compute a speculative root.
+///
+/// Reads see the pending writes first, then fall back to the wrapped store. The pending writes are written
+/// to the wrapped store by `flush`, and dropped without side effects by `discard` or when the overlay is dropped.
+pub struct OverlayStore<S, V> {
+    inner: S,
+    // The pending branches and leaves, `None` for a pending remove.
+    branches: HashMap<BranchKey, Option<BranchNode>>,
+    leaves: HashMap<H256, Option<V>>,
+}
+
+impl<S, V> OverlayStore<S, V> {
+    pub fn new(inner: S) -> Self {
+        OverlayStore {
+            inner,
+            branches: HashMap::new(),
+            leaves: HashMap::new(),
+        }
+    }
+
+    /// The wrapped store, which does not see the pending writes.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Drop the pending writes and return the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the number of pending branch and leaf writes.
+    pub fn len(&self) -> usize {
+        self.branches.len() + self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty() && self.leaves.is_empty()
+    }
+
+    /// Drop all pending writes.
+    pub fn discard(&mut self) {
+        self.branches.clear();
+        self.leaves.clear();
+    }
+
+    /// Write the pending writes to the wrapped store.
+    ///
+    /// The pending writes are kept until all of them are written, so a failed flush can be retried. The writes
+    /// are applied one by one, so an error leaves part of them in the wrapped store; wrap a transaction store,
+    /// or use a `batch_store::WriteBatchStore`, to apply them atomically.
+    pub fn flush(&mut self) -> Result<(), Error>
+    where
+        S: StoreWriteOps<V>,
+        V: Clone,
+    {
+        for (node_key, branch) in &self.branches {
+            match branch {
+                Some(branch) => self.inner.insert_branch(node_key.clone(), branch.clone())?,
+                None => self.inner.remove_branch(node_key)?,
+            }
+        }
+        for (leaf_key, leaf) in &self.leaves {
+            match leaf {
+                Some(leaf) => self.inner.insert_leaf(*leaf_key, leaf.clone())?,
+                None => self.inner.remove_leaf(leaf_key)?,
+            }
+        }
+        self.discard();
+        Ok(())
+    }
+}
+
+impl<S, V> StoreReadOps<V> for OverlayStore<S, V>
+where
+    S: StoreReadOps<V>,
+    V: Clone,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        match self.branches.get(branch_key) {
+            Some(branch) => Ok(branch.clone()),
+            None => self.inner.get_branch(branch_key),
+        }
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        match self.leaves.get(leaf_key) {
+            Some(leaf) => Ok(leaf.clone()),
+            None => self.inner.get_leaf(leaf_key),
+        }
+    }
+}
+
+impl<S, V> StoreWriteOps<V> for OverlayStore<S, V> {
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        self.branches.insert(node_key, Some(branch));
+        Ok(())
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.leaves.insert(leaf_key, Some(leaf));
+        Ok(())
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        self.branches.insert(node_key.clone(), None);
+        Ok(())
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.leaves.insert(*leaf_key, None);
+        Ok(())
+    }
+}
+
+/// Open a tree at the last committed root of `store`, keeping its updates in an `OverlayStore`.
+pub fn open_tree<H, V, S>(store: S) -> Result<SparseMerkleTree<H, V, OverlayStore<S, V>>, Error>
+where
+    H: Hasher + Default,
+    V: Value + Clone,
+    S: Backend + StoreReadOps<V>,
+    S::DB: GetCF<ReadOptions>,
+{
+    open_tree_with(store, OverlayStore::new)
+}
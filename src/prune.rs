@@ -0,0 +1,202 @@
+use rocksdb::{
+    prelude::{IterateCF, WriteOps},
+    Direction, WriteBatch,
+};
+use sparse_merkle_tree::{error::Error, H256};
+
+use crate::{
+    backend::{KeySpace, KeyValue},
+    versioned::{
+        pending_key, root_key, slice_to_version, VersionedStore, BRANCH_TAG, LEAF_TAG, PRESENT,
+    },
+};
+
+/// An incremental pruning of the versions of a `versioned::VersionedStore` which are not retained.
+///
+/// `start` deletes the root records of the pruned versions, so they can no longer be opened, then every `step`
+/// deletes the node records of a part of the keys which no retained version reads, along with the records of the
+/// versions up to the last retained version which were written but not committed. Records written after the
+/// last retained version are kept, so a new version can be written while pruning. Readers of a pruned version
+/// which opened it before `start` should read from a snapshot, the records of the retained versions are never
+/// deleted. An interrupted pruning can be started again with the same roots.
+pub struct Pruner<'a, T> {
+    db: &'a T,
+    space: KeySpace<'a>,
+    // The retained versions, in ascending order.
+    retained: Vec<u64>,
+    // The versions up to the last retained version which were written but not committed, their records are deleted.
+    uncommitted: Vec<u64>,
+    // The key, without the prefix of the space, from which the next step scans the node records,
+    // `None` when all records have been scanned.
+    cursor: Option<Vec<u8>>,
+}
+
+impl<'a, T> Pruner<'a, T>
+where
+    T: IterateCF + WriteOps,
+{
+    /// Start pruning all committed versions of `store` except the versions committed with one of `roots`.
+    ///
+    /// Returns an error if a root was not committed, or if the last committed version is not retained.
+    pub fn start<W>(store: &VersionedStore<'a, T, W>, roots: &[H256]) -> Result<Self, Error> {
+        let versions = store.versions()?;
+        if let Some(root) = roots
+            .iter()
+            .find(|root| versions.iter().all(|(_, committed)| committed != *root))
+        {
+            return Err(Error::Store(format!(
+                "root {:?} is not the root of a committed version",
+                root
+            )));
+        }
+        if let Some((version, root)) = versions.last() {
+            if !roots.contains(root) {
+                return Err(Error::Store(format!(
+                    "the last committed version {} must be retained",
+                    version
+                )));
+            }
+        }
+
+        let space = store.space();
+        let mut retained = Vec::new();
+        let mut batch = WriteBatch::default();
+        for (version, root) in versions {
+            if roots.contains(&root) {
+                retained.push(version);
+            } else {
+                space.batch_delete(&mut batch, &root_key(version))?;
+                space.batch_delete(&mut batch, &pending_key(version))?;
+            }
+        }
+        let last_retained = retained.last().copied().unwrap_or_default();
+        let mut uncommitted = store.uncommitted_versions()?;
+        uncommitted.retain(|&version| version <= last_retained);
+        store
+            .db()
+            .write_full(&batch, None)
+            .map_err(|e| Error::Store(e.to_string()))?;
+        Ok(Pruner {
+            db: store.db(),
+            space,
+            retained,
+            uncommitted,
+            cursor: Some(vec![BRANCH_TAG]),
+        })
+    }
+
+    /// Returns true when the records of all keys have been pruned.
+    pub fn is_done(&self) -> bool {
+        self.cursor.is_none()
+    }
+
+    /// Prune the records of up to `limit` node keys in one write batch, returns the number of deleted records.
+    pub fn step(&mut self, limit: usize) -> Result<usize, Error> {
+        let limit = limit.max(1);
+        let cursor = match self.cursor.take() {
+            Some(cursor) => cursor,
+            None => return Ok(0),
+        };
+        let prefix_len = self.space.prefix.len();
+        let mut records = self
+            .space
+            .scan_from(self.db, Some(&cursor), Direction::Forward, None)?
+            .take_while(|(key, _)| {
+                matches!(key.get(prefix_len), Some(&BRANCH_TAG) | Some(&LEAF_TAG))
+            })
+            .peekable();
+
+        let mut batch = WriteBatch::default();
+        let mut deleted = 0;
+        let mut keys = 0;
+        let mut group: Vec<KeyValue> = Vec::new();
+        while let Some((key, value)) = records.next() {
+            if keys == limit {
+                self.cursor = Some(key[prefix_len..].to_vec());
+                break;
+            }
+            let node_key_len = key.len().saturating_sub(8);
+            let last_of_group = match records.peek() {
+                Some((next, _)) => {
+                    next.len() != key.len() || next[..node_key_len] != key[..node_key_len]
+                }
+                None => true,
+            };
+            group.push((key, value));
+            if last_of_group {
+                for key in self.unreachable(&group)? {
+                    KeySpace::new(self.space.col, &[]).batch_delete(&mut batch, key)?;
+                    deleted += 1;
+                }
+                group.clear();
+                keys += 1;
+            }
+        }
+        if self.cursor.is_none() {
+            for version in &self.uncommitted {
+                self.space
+                    .batch_delete(&mut batch, &pending_key(*version))?;
+            }
+        }
+        self.db
+            .write_full(&batch, None)
+            .map_err(|e| Error::Store(e.to_string()))?;
+        Ok(deleted)
+    }
+
+    /// Prune the records of all remaining keys, in steps of `limit` keys, returns the number of deleted records.
+    pub fn run(&mut self, limit: usize) -> Result<usize, Error> {
+        let mut deleted = 0;
+        while !self.is_done() {
+            deleted += self.step(limit)?;
+        }
+        Ok(deleted)
+    }
+
+    // The keys of the records of a node key, ordered by version, which no retained version reads.
+    fn unreachable<'b>(&self, records: &'b [KeyValue]) -> Result<Vec<&'b [u8]>, Error> {
+        let mut unreachable = Vec::new();
+        // the records of the versions which were not committed are not read by any version
+        let mut committed = Vec::new();
+        for record @ (key, _) in records {
+            if self
+                .uncommitted
+                .contains(&slice_to_version(&key[key.len() - 8..])?)
+            {
+                unreachable.push(key.as_ref());
+            } else {
+                committed.push(record);
+            }
+        }
+        let records = committed;
+        // whether the last kept record is a node, which a later removal record must hide
+        let mut kept_node = false;
+        for (i, (key, value)) in records.iter().enumerate() {
+            let version = slice_to_version(&key[key.len() - 8..])?;
+            let next_version = match records.get(i + 1) {
+                Some((next, _)) => Some(slice_to_version(&next[next.len() - 8..])?),
+                None => None,
+            };
+            // the record is read by the versions from its version to the version of the next record
+            let read = self.retained.iter().any(|&retained| {
+                retained >= version
+                    && match next_version {
+                        Some(next_version) => retained < next_version,
+                        None => true,
+                    }
+            });
+            let present = value.first() == Some(&PRESENT);
+            let keep = match self.retained.last() {
+                Some(&last) if version <= last => read && (present || kept_node),
+                // written after the last retained version
+                _ => true,
+            };
+            if keep {
+                kept_node = present;
+            } else {
+                unreachable.push(key.as_ref());
+            }
+        }
+        Ok(unreachable)
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+#[cfg(feature = "trie")]
+use sparse_merkle_tree::merge::MergeValue;
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, Value},
+    BranchKey, MerkleProof, SparseMerkleTree, H256,
+};
+
+/// The leaves of a tree in an inclusive key range, with a proof that they are all the non-zero leaves of the range.
+///
+/// The range follows the order of `H256::cmp`, which is the order of the leaves in the tree.
+#[derive(Debug, Clone)]
+pub struct RangeProof<V> {
+    pub start: H256,
+    pub end: H256,
+    /// The leaves of the range, ordered by key.
+    pub leaves: Vec<(H256, V)>,
+    /// A proof of the leaves and of the keys `start` and `end`, which are zero when they are not leaves.
+    pub proof: MerkleProof,
+}
+
+/// Query the leaves of `tree` with keys from `start` to `end` inclusive, and prove that none is omitted.
+///
+/// The leaves are found by walking down from the root branch into the subtrees which overlap the range, so the query
+/// reads the branches on the paths of the leaves of the range, and of the subtrees at the edges of the range.
+pub fn range_proof<H, V, S>(
+    tree: &SparseMerkleTree<H, V, S>,
+    start: H256,
+    end: H256,
+) -> Result<RangeProof<V>, Error>
+where
+    H: Hasher + Default,
+    V: Value,
+    S: StoreReadOps<V>,
+{
+    if start > end {
+        return Err(Error::Store(format!(
+            "invalid range, start {:?} is after end {:?}",
+            start, end
+        )));
+    }
+    let mut leaf_keys = Vec::new();
+    let mut branch_keys = vec![BranchKey::new(u8::MAX, H256::zero())];
+    while let Some(branch_key) = branch_keys.pop() {
+        let height = branch_key.height;
+        let branch = match tree.store().get_branch(&branch_key)? {
+            Some(branch) => branch,
+            // an empty tree
+            None if height == u8::MAX => break,
+            None => return Err(Error::MissingBranch(height, branch_key.node_key)),
+        };
+        for (is_right, child) in [(false, branch.left), (true, branch.right)] {
+            let mut path = branch_key.node_key;
+            if is_right {
+                path.set_bit(height);
+            }
+            let (first, last) = subtree_range(&path, height);
+            if child.is_zero() || first > end || last < start {
+                continue;
+            }
+            match child {
+                #[cfg(feature = "trie")]
+                MergeValue::ShortCut { key, .. } => {
+                    if key >= start && key <= end {
+                        leaf_keys.push(key);
+                    }
+                }
+                _ if height == 0 => leaf_keys.push(path),
+                _ => branch_keys.push(BranchKey::new(height - 1, path)),
+            }
+        }
+    }
+    leaf_keys.sort_unstable();
+    let leaves = leaf_keys
+        .into_iter()
+        .map(|key| {
+            let leaf = tree
+                .store()
+                .get_leaf(&key)?
+                .ok_or(Error::MissingLeaf(key))?;
+            Ok((key, leaf))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut keys: Vec<H256> = leaves.iter().map(|(key, _)| *key).collect();
+    keys.extend([start, end]);
+    keys.sort_unstable();
+    keys.dedup();
+    let proof = tree.merkle_proof(keys)?;
+    Ok(RangeProof {
+        start,
+        end,
+        leaves,
+        proof,
+    })
+}
+
+impl<V: Value> RangeProof<V> {
+    /// Verify that the leaves are all the non-zero leaves of the range in the tree of `root`.
+    ///
+    /// Returns an error when the proof does not match the number of leaves, like `MerkleProof::verify`.
+    pub fn verify<H: Hasher + Default>(&self, root: &H256) -> Result<bool, Error> {
+        if self.start > self.end
+            || self
+                .leaves
+                .iter()
+                .any(|(key, _)| *key < self.start || *key > self.end)
+            || self.leaves.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+        {
+            return Ok(false);
+        }
+        let mut leaves: BTreeMap<H256, H256> =
+            [(self.start, H256::zero()), (self.end, H256::zero())]
+                .into_iter()
+                .collect();
+        leaves.extend(
+            self.leaves
+                .iter()
+                .map(|(key, value)| (*key, value.to_h256())),
+        );
+        let keys: Vec<H256> = leaves.keys().copied().collect();
+        if !self
+            .proof
+            .clone()
+            .verify::<H>(root, leaves.into_iter().collect())?
+        {
+            return Ok(false);
+        }
+        Ok(is_complete(
+            &keys,
+            self.proof.leaves_bitmap(),
+            &self.start,
+            &self.end,
+        ))
+    }
+}
+
+// Check that every sibling subtree in the proof of the sorted `keys` which overlaps the range is zero, visiting the
+// siblings like `MerkleProof::compile`. The bitmap of a verified proof is set for the non-zero siblings, and every
+// key of the range which is not proven, e.g. an omitted leaf, is in one of the siblings.
+fn is_complete(keys: &[H256], leaves_bitmap: &[H256], start: &H256, end: &H256) -> bool {
+    let mut stack = Vec::new();
+    for (i, (key, bitmap)) in keys.iter().zip(leaves_bitmap).enumerate() {
+        let fork_height = match keys.get(i + 1) {
+            Some(next) => key.fork_height(next),
+            None => u8::MAX,
+        };
+        for height in 0..=fork_height {
+            if height == fork_height && i + 1 < keys.len() {
+                // merged with the next keys at the fork
+                break;
+            }
+            if stack.last() == Some(&height) {
+                // merged with the previous keys
+                stack.pop();
+                continue;
+            }
+            if bitmap.get_bit(height) {
+                let (first, last) = sibling_range(key, height);
+                if first <= *end && last >= *start {
+                    return false;
+                }
+            }
+        }
+        stack.push(fork_height);
+    }
+    true
+}
+
+// The first and the last key of the sibling subtree of `key` at `height`.
+fn sibling_range(key: &H256, height: u8) -> (H256, H256) {
+    let mut path = key.parent_path(height);
+    if !key.is_right(height) {
+        path.set_bit(height);
+    }
+    subtree_range(&path, height)
+}
+
+// The first and the last key of the subtree at `height` whose keys start with the bits of `path` above `height`.
+fn subtree_range(path: &H256, height: u8) -> (H256, H256) {
+    let first = *path;
+    let mut last = first;
+    for height in 0..height {
+        last.set_bit(height);
+    }
+    (first, last)
+}
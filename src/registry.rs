@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rocksdb::{
+    prelude::{DeleteCF, GetCF, IterateCF, PutCF},
+    ReadOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, StoreWriteOps, Value},
+    SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::{Backend, KeySpace},
+    drop::{delete_store, DeleteRange},
+    format::StoreFormat,
+    namespace::Namespace,
+    root::write_root,
+};
+
+/// The name of the namespace holding the registry records, in the default column family.
+pub const REGISTRY_NAME: &[u8] = b"smt:registry";
+
+const TREE_KEY_PREFIX: &[u8] = b"tree:";
+const NEXT_ID_KEY: &[u8] = b"next_id";
+const TREE_INFO_LEN: usize = 4 + 8 + 32 + 8 + 2;
+
+/// The registry record of a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeInfo {
+    pub name: Vec<u8>,
+    /// The internal ID of the tree, its data is stored under the namespace of the ID, see `namespace`.
+    pub id: u32,
+    /// Creation time in seconds since the UNIX epoch.
+    pub created_at: u64,
+    /// The last root recorded by `Registry::update_tree`.
+    pub root: H256,
+    /// The number of non-zero leaves of the tree at `root`.
+    pub leaf_count: u64,
+    /// The format of the tree when it was created.
+    pub format: StoreFormat,
+}
+
+impl TreeInfo {
+    /// The namespace of the tree data, use it to construct the store of the tree,
+    /// e.g. with `DefaultStoreMultiTree::with_namespace`.
+    pub fn namespace(&self) -> Namespace {
+        Namespace::new(&self.id.to_be_bytes()).expect("4 bytes name")
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        [
+            &self.id.to_be_bytes()[..],
+            &self.created_at.to_be_bytes(),
+            self.root.as_slice(),
+            &self.leaf_count.to_be_bytes(),
+            &self.format.to_vec(),
+        ]
+        .concat()
+    }
+
+    fn from_slice(name: &[u8], slice: &[u8]) -> Result<Self, Error> {
+        if slice.len() != TREE_INFO_LEN {
+            return Err(Error::Store(format!(
+                "invalid registry record of tree {:?}: {:?}",
+                name, slice
+            )));
+        }
+        let root: [u8; 32] = slice[12..44].try_into().expect("checked length");
+        Ok(TreeInfo {
+            name: name.to_vec(),
+            id: u32::from_be_bytes(slice[0..4].try_into().expect("checked length")),
+            created_at: u64::from_be_bytes(slice[4..12].try_into().expect("checked length")),
+            root: root.into(),
+            leaf_count: u64::from_be_bytes(slice[44..52].try_into().expect("checked length")),
+            format: StoreFormat::from_slice(&slice[52..])?,
+        })
+    }
+}
+
+/// A registry of the trees of a multi-tree database, which records their names, IDs, roots and leaf counts.
+///
+/// The trees are stored under the namespaces of their IDs, with `DefaultStoreMultiTree` or
+/// `ColumnFamilyStoreMultiTree`, which must not share the default column family with stores of other prefixes.
+/// Use the same transaction for the registry and the stores to update a tree and its record atomically,
+/// creating trees concurrently on a plain DB may assign the same ID twice.
+pub struct Registry<'a, T, W> {
+    // The RocksDB database which stores the records, can be a `DB` / `OptimisticTransaction` / `Snapshot` etc.
+    inner: &'a T,
+    namespace: Namespace,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<&'a ReadOptions>,
+    // The write options of every put and delete, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<&'a W>,
+}
+
+impl<'a, T, W> Registry<'a, T, W> {
+    pub fn new(db: &'a T) -> Self {
+        Registry {
+            inner: db,
+            namespace: Namespace::new(REGISTRY_NAME).expect("short name"),
+            read_options: None,
+            write_options: None,
+        }
+    }
+
+    /// Use `read_options` for every read of this registry.
+    pub fn with_read_options(mut self, read_options: &'a ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this registry.
+    pub fn with_write_options(mut self, write_options: &'a W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    fn space(&self) -> KeySpace<'_> {
+        KeySpace::new(None, self.namespace.as_bytes())
+    }
+
+    /// Look up the record of a tree, returns `None` if there is no tree with this name.
+    pub fn get(&self, name: &[u8]) -> Result<Option<TreeInfo>, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        self.space()
+            .get(
+                self.inner,
+                &[TREE_KEY_PREFIX, name].concat(),
+                self.read_options,
+            )?
+            .map(|record| TreeInfo::from_slice(name, &record))
+            .transpose()
+    }
+
+    /// List the records of all trees, ordered by name.
+    pub fn list(&self) -> Result<Vec<TreeInfo>, Error>
+    where
+        T: IterateCF,
+    {
+        let space = self.space();
+        let key_prefix = space.key(TREE_KEY_PREFIX);
+        space
+            .scan(self.inner, self.read_options)?
+            .filter(|(key, _)| key.starts_with(&key_prefix))
+            .map(|(key, record)| TreeInfo::from_slice(&key[key_prefix.len()..], &record))
+            .collect()
+    }
+
+    /// Create an empty tree, returns an error if a tree with this name exists.
+    pub fn create(&self, name: &[u8]) -> Result<TreeInfo, Error>
+    where
+        T: GetCF<ReadOptions> + PutCF<W>,
+    {
+        if self.get(name)?.is_some() {
+            return Err(Error::Store(format!("tree {:?} already exists", name)));
+        }
+        let space = self.space();
+        let id = match space.get(self.inner, NEXT_ID_KEY, self.read_options)? {
+            Some(id) => u32::from_be_bytes(id.as_ref().try_into().map_err(|_| {
+                Error::Store(format!("invalid registry next id: {:?}", id.as_ref()))
+            })?),
+            None => 0,
+        };
+        let next_id = id
+            .checked_add(1)
+            .ok_or_else(|| Error::Store("registry is out of tree ids".to_string()))?;
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::Store(e.to_string()))?
+            .as_secs();
+        let info = TreeInfo {
+            name: name.to_vec(),
+            id,
+            created_at,
+            root: H256::zero(),
+            leaf_count: 0,
+            format: StoreFormat::current(),
+        };
+        space.put(
+            self.inner,
+            NEXT_ID_KEY,
+            &next_id.to_be_bytes(),
+            self.write_options,
+        )?;
+        self.put(&info)?;
+        Ok(info)
+    }
+
+    /// Apply `leaves` to the tree of `name` like `SparseMerkleTree::update_all`, then record the new root in the
+    /// store of the tree, see `root::commit_root`, and the new root and leaf count in the registry.
+    pub fn update_tree<H, V, S>(
+        &self,
+        name: &[u8],
+        tree: &mut SparseMerkleTree<H, V, S>,
+        leaves: Vec<(H256, V)>,
+    ) -> Result<TreeInfo, Error>
+    where
+        T: GetCF<ReadOptions> + PutCF<W>,
+        H: Hasher + Default,
+        V: Value,
+        S: Backend + StoreReadOps<V> + StoreWriteOps<V>,
+        S::DB: PutCF<S::WriteOptions>,
+    {
+        let mut info = self.get_tree(name)?;
+        check_store(&info, tree.store())?;
+        // the last value of a key is applied, like `update_all` does
+        let updates: HashMap<H256, bool> = leaves
+            .iter()
+            .map(|(key, value)| (*key, !value.to_h256().is_zero()))
+            .collect();
+        let mut leaf_count = info.leaf_count as i64;
+        for (key, non_zero) in updates {
+            let existed = !tree.get(&key)?.to_h256().is_zero();
+            leaf_count += non_zero as i64 - existed as i64;
+        }
+        tree.update_all(leaves)?;
+        write_root(tree.store(), tree.root())?;
+        info.root = *tree.root();
+        info.leaf_count = leaf_count as u64;
+        self.put(&info)?;
+        Ok(info)
+    }
+
+    /// Delete a tree, its data is deleted from `store`, which must be the store of the tree.
+    pub fn delete<S>(&self, name: &[u8], store: &S) -> Result<(), Error>
+    where
+        T: GetCF<ReadOptions> + DeleteCF<W>,
+        S: Backend,
+        S::DB: IterateCF + DeleteRange<S::WriteOptions>,
+    {
+        let info = self.get_tree(name)?;
+        check_store(&info, store)?;
+        delete_store(store, false)?;
+        self.space().delete(
+            self.inner,
+            &[TREE_KEY_PREFIX, name].concat(),
+            self.write_options,
+        )
+    }
+
+    fn get_tree(&self, name: &[u8]) -> Result<TreeInfo, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        self.get(name)?
+            .ok_or_else(|| Error::Store(format!("tree {:?} does not exist", name)))
+    }
+
+    fn put(&self, info: &TreeInfo) -> Result<(), Error>
+    where
+        T: PutCF<W>,
+    {
+        self.space().put(
+            self.inner,
+            &[TREE_KEY_PREFIX, &info.name].concat(),
+            &info.to_vec(),
+            self.write_options,
+        )
+    }
+}
+
+// Refuse a store which does not hold the data of the tree.
+fn check_store<S: Backend>(info: &TreeInfo, store: &S) -> Result<(), Error> {
+    let namespace = info.namespace();
+    if store.branch_space().prefix != namespace.as_bytes()
+        || store.leaf_space().prefix != namespace.as_bytes()
+    {
+        return Err(Error::Store(format!(
+            "store is not the store of tree {:?}, construct it with the namespace of the tree",
+            info.name
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std::marker::PhantomData;
+
+use rocksdb::{
+    prelude::{GetCF, IterateCF, WriteOps},
+    Direction, ReadOptions, WriteBatch, WriteOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    merge::{merge, MergeValue},
+    traits::{Hasher, Value},
+    BranchKey, BranchNode, H256,
+};
+
+use crate::{
+    backend::Backend,
+    format::{is_root_branch_key, missing_format_record, FORMAT_KEY},
+    iter::StoreIterOps,
+    root::ROOT_KEY,
+    serde::{branch_key_to_vec, branch_node_to_vec},
+};
+
+// The number of records deleted or written per write batch.
+const REBUILD_BATCH_SIZE: usize = 10_000;
+
+// A subtree of the leaves built so far, which is not yet stored in a branch.
+struct Node {
+    // The key of a leaf of the subtree.
+    key: H256,
+    value: MergeValue,
+    // The height of the next branch above the subtree, 0 for a leaf, 256 for the whole tree.
+    level: u16,
+}
+
+/// Computes the branches of a tree bottom-up from its leaves in the order of the tree, in a single pass.
+///
+/// Every branch is passed to `write` once, when both of its subtrees are complete. The branches are the ones
+/// an empty tree updated with the same leaves would store.
+pub(crate) struct BranchBuilder<H, F> {
+    write: F,
+    // The subtrees waiting for the subtree on their right, with the height of the fork between them.
+    stack: Vec<(Node, u8)>,
+    // The last leaf, which is joined once the fork with the next leaf is known.
+    last: Option<Node>,
+    phantom: PhantomData<H>,
+}
+
+impl<H, F> BranchBuilder<H, F>
+where
+    H: Hasher + Default,
+    F: FnMut(BranchKey, BranchNode) -> Result<(), Error>,
+{
+    pub(crate) fn new(write: F) -> Self {
+        BranchBuilder {
+            write,
+            stack: Vec::new(),
+            last: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Add the next leaf, whose key must be after the keys of the previous leaves in the order of `H256::cmp`.
+    /// A zero `value` is skipped.
+    pub(crate) fn push(&mut self, key: H256, value: H256) -> Result<(), Error> {
+        if value.is_zero() {
+            return Ok(());
+        }
+        if let Some(last) = self.last.take() {
+            if key <= last.key {
+                return Err(Error::Store(format!(
+                    "leaf {:?} is not after the previous leaf {:?}",
+                    key, last.key
+                )));
+            }
+            let fork = last.key.fork_height(&key);
+            let node = self.join_left(last, Some(fork))?;
+            self.stack.push((node, fork));
+        }
+        self.last = Some(Node {
+            key,
+            value: MergeValue::from_h256(value),
+            level: 0,
+        });
+        Ok(())
+    }
+
+    /// Write the remaining branches up to the root branch, returns the root.
+    pub(crate) fn finish(mut self) -> Result<H256, Error> {
+        let mut node = match self.last.take() {
+            Some(last) => self.join_left(last, None)?,
+            None => return Ok(H256::zero()),
+        };
+        if node.level > u16::from(u8::MAX) {
+            return Ok(node.value.hash::<H>());
+        }
+        let value = self.place(&mut node, u8::MAX)?;
+        let (left, right) = if node.key.is_right(u8::MAX) {
+            (MergeValue::zero(), value)
+        } else {
+            (value, MergeValue::zero())
+        };
+        let root = merge::<H>(u8::MAX, &H256::zero(), &left, &right).hash::<H>();
+        (self.write)(
+            BranchKey::new(u8::MAX, H256::zero()),
+            BranchNode { left, right },
+        )?;
+        Ok(root)
+    }
+
+    // Join the subtrees on the left of `node` which fork below the next leaf, `None` if there is no next leaf.
+    fn join_left(&mut self, mut node: Node, next_fork: Option<u8>) -> Result<Node, Error> {
+        while let Some(&(_, fork)) = self.stack.last() {
+            if matches!(next_fork, Some(next_fork) if next_fork < fork) {
+                break;
+            }
+            let (left, fork) = self.stack.pop().expect("checked stack is not empty");
+            node = self.join(left, node, fork)?;
+        }
+        Ok(node)
+    }
+
+    // Returns the value of a subtree in the branch at `height` above it, writing the branches with a single child
+    // between them.
+    fn place(&mut self, node: &mut Node, height: u8) -> Result<MergeValue, Error> {
+        #[cfg(feature = "trie")]
+        if node.level == 0 {
+            // a single leaf is a shortcut of the branch above it, without branches between them
+            return Ok(MergeValue::shortcut_or_value(
+                node.key,
+                node.value.hash::<H>(),
+                height,
+            ));
+        }
+        while node.level < u16::from(height) {
+            let level = node.level as u8;
+            let node_key = node.key.parent_path(level);
+            let (left, right) = if node.key.is_right(level) {
+                (MergeValue::zero(), node.value.clone())
+            } else {
+                (node.value.clone(), MergeValue::zero())
+            };
+            node.value = merge::<H>(level, &node_key, &left, &right);
+            (self.write)(BranchKey::new(level, node_key), BranchNode { left, right })?;
+            node.level += 1;
+        }
+        Ok(node.value.clone())
+    }
+
+    // Join two adjacent subtrees in the branch at their fork height.
+    fn join(&mut self, mut left: Node, mut right: Node, height: u8) -> Result<Node, Error> {
+        let left_value = self.place(&mut left, height)?;
+        let right_value = self.place(&mut right, height)?;
+        let node_key = right.key.parent_path(height);
+        let value = merge::<H>(height, &node_key, &left_value, &right_value);
+        (self.write)(
+            BranchKey::new(height, node_key),
+            BranchNode {
+                left: left_value,
+                right: right_value,
+            },
+        )?;
+        Ok(Node {
+            key: right.key,
+            value,
+            level: u16::from(height) + 1,
+        })
+    }
+}
+
+// Writes the records of a store in write batches.
+pub(crate) struct BatchWriter<'a, S> {
+    store: &'a S,
+    pub(crate) batch: WriteBatch,
+    batch_len: usize,
+}
+
+impl<'a, S> BatchWriter<'a, S>
+where
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + WriteOps,
+{
+    pub(crate) fn new(store: &'a S) -> Self {
+        BatchWriter {
+            store,
+            batch: WriteBatch::default(),
+            batch_len: 0,
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<(), Error> {
+        self.store
+            .db()
+            .write_full(&self.batch, self.store.write_options())
+            .map_err(|e| Error::Store(e.to_string()))?;
+        self.batch = WriteBatch::default();
+        self.batch_len = 0;
+        Ok(())
+    }
+
+    // Count a record added to the batch, writes the batch when it is full.
+    pub(crate) fn added(&mut self) -> Result<(), Error> {
+        self.batch_len += 1;
+        if self.batch_len == REBUILD_BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_branch(
+        &mut self,
+        branch_key: BranchKey,
+        branch: BranchNode,
+    ) -> Result<(), Error> {
+        let space = self.store.branch_space();
+        if is_root_branch_key(&branch_key) {
+            let record = space.get(self.store.db(), FORMAT_KEY, self.store.read_options())?;
+            if let Some(record) = missing_format_record(record.as_deref())? {
+                space.batch_put(&mut self.batch, FORMAT_KEY, &record)?;
+            }
+        }
+        space.batch_put(
+            &mut self.batch,
+            &branch_key_to_vec(&branch_key),
+            &branch_node_to_vec(&branch),
+        )?;
+        self.added()
+    }
+}
+
+/// Rebuild all branches of the tree of a store from its leaves, and record the new root, returns the root.
+///
+/// Deletes the stored branches, which may be missing or corrupted, then computes the branches bottom-up from the
+/// leaves in a single pass, writing them in write batches. The keys and the hashes of the leaves are sorted in
+/// memory, in the order of the tree. The store must not be used until the rebuild is complete, an interrupted
+/// rebuild can be run again.
+pub fn rebuild_branches<H, V, S>(store: &S) -> Result<H256, Error>
+where
+    H: Hasher + Default,
+    V: Value + From<Box<[u8]>>,
+    S: Backend<WriteOptions = WriteOptions>,
+    S::DB: GetCF<ReadOptions> + IterateCF + WriteOps,
+{
+    let mut leaves: Vec<(H256, H256)> = store
+        .leaves::<V>(None, Direction::Forward)?
+        .map(|(key, value)| (key, value.to_h256()))
+        .collect();
+    leaves.sort_unstable_by_key(|(key, _)| *key);
+
+    let mut writer = BatchWriter::new(store);
+    let space = store.branch_space();
+    // the branch keys are read raw, so that corrupted branches are deleted too
+    for (key, _) in space.iter(store.db(), 33, store.read_options())? {
+        let key = &key[space.prefix.len()..];
+        space.batch_delete(&mut writer.batch, key)?;
+        writer.added()?;
+    }
+    writer.flush()?;
+
+    let mut builder =
+        BranchBuilder::<H, _>::new(|branch_key, branch| writer.write_branch(branch_key, branch));
+    for (key, value) in leaves {
+        builder.push(key, value)?;
+    }
+    let root = builder.finish()?;
+    space.batch_put(&mut writer.batch, ROOT_KEY, root.as_slice())?;
+    writer.flush()?;
+    Ok(root)
+}
@@ -0,0 +1,97 @@
+use rocksdb::{
+    prelude::{GetCF, PutCF},
+    ReadOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, Value},
+    SparseMerkleTree, H256,
+};
+
+use crate::backend::Backend;
+
+/// Reserved key of the root record, stored in the branch key space of a store.
+pub const ROOT_KEY: &[u8] = b"smt:root";
+
+/// Read the last committed root of a store, returns `None` if no root was committed.
+pub fn read_root<S>(store: &S) -> Result<Option<H256>, Error>
+where
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+{
+    match store
+        .branch_space()
+        .get(store.db(), ROOT_KEY, store.read_options())?
+    {
+        Some(root) => {
+            let root: [u8; 32] = root
+                .as_ref()
+                .try_into()
+                .map_err(|_| Error::Store(format!("invalid root record: {:?}", root.as_ref())))?;
+            Ok(Some(root.into()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Record `root` as the committed root of a store.
+pub fn write_root<S>(store: &S, root: &H256) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: PutCF<S::WriteOptions>,
+{
+    store
+        .branch_space()
+        .put(store.db(), ROOT_KEY, root.as_slice(), store.write_options())
+}
+
+/// Reopen a tree at the last committed root of its store.
+///
+/// The root is computed from the stored root branch and checked against the committed root, returns an error
+/// if they differ, e.g. after the branches were written without `commit_root`. If no root was committed,
+/// e.g. for a store written before the root record was introduced, the computed root is used.
+pub fn open_tree<H, V, S>(store: S) -> Result<SparseMerkleTree<H, V, S>, Error>
+where
+    H: Hasher + Default,
+    V: Value,
+    S: Backend + StoreReadOps<V>,
+    S::DB: GetCF<ReadOptions>,
+{
+    open_tree_with(store, |store| store)
+}
+
+/// Reopen a tree at the last committed root of `store` like `open_tree`, with the store wrapped by `wrap`.
+pub(crate) fn open_tree_with<H, V, S, W, F>(
+    store: S,
+    wrap: F,
+) -> Result<SparseMerkleTree<H, V, W>, Error>
+where
+    H: Hasher + Default,
+    V: Value,
+    S: Backend,
+    S::DB: GetCF<ReadOptions>,
+    W: StoreReadOps<V>,
+    F: FnOnce(S) -> W,
+{
+    let committed = read_root(&store)?;
+    let tree = SparseMerkleTree::new_with_store(wrap(store))?;
+    match committed {
+        Some(root) if root != *tree.root() => Err(Error::Store(format!(
+            "the committed root {:?} does not match the root {:?} of the stored branches",
+            root,
+            tree.root()
+        ))),
+        _ => Ok(tree),
+    }
+}
+
+/// Record the current root of a tree in its store, so the tree can be reopened with `open_tree`.
+///
+/// When the store writes into a transaction, call this before committing the transaction.
+pub fn commit_root<H, V, S>(tree: &SparseMerkleTree<H, V, S>) -> Result<(), Error>
+where
+    S: Backend,
+    S::DB: PutCF<S::WriteOptions>,
+{
+    write_root(tree.store(), tree.root())
+}
@@ -1,7 +1,83 @@
-use std::convert::TryInto;
+use std::{convert::TryInto, fmt};
 
 use sparse_merkle_tree::{merge::MergeValue, BranchKey, BranchNode};
 
+/// The legacy format, a branch node value starts with its tag byte.
+pub const BRANCH_NODE_FORMAT_V1: u8 = 1;
+/// A branch node value starts with a version header byte followed by the tagged encoding.
+pub const BRANCH_NODE_FORMAT_V2: u8 = 2;
+/// The format version written by `branch_node_to_vec`.
+pub const BRANCH_NODE_FORMAT_VERSION: u8 = BRANCH_NODE_FORMAT_V2;
+
+/// The length of the longest encoded branch node, a version header, a tag and two merge values of 65 bytes.
+pub const MAX_BRANCH_NODE_LEN: usize = 132;
+
+// The high bit marks a version header, tags of the legacy format never set it.
+const VERSION_HEADER: u8 = 0x80;
+
+/// Errors returned when a stored value can not be decoded into a `BranchNode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The value was written with a format version this build can not read.
+    UnsupportedVersion(u8),
+    /// The tag byte does not match any known `MergeValue` combination.
+    UnknownTag(u8),
+    /// The tag byte contains a `MergeValue::ShortCut`, which requires the `trie` feature.
+    TrieNotEnabled(u8),
+    /// The value is shorter than its tag requires.
+    InvalidLength { expected: usize, actual: usize },
+    /// The value has extra bytes after the encoded node.
+    TrailingBytes { expected: usize, actual: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported branch node format version: {}", version)
+            }
+            DecodeError::UnknownTag(tag) => write!(f, "unknown branch node tag: {}", tag),
+            DecodeError::TrieNotEnabled(tag) => write!(
+                f,
+                "branch node tag {} requires the `trie` feature, which is not enabled",
+                tag
+            ),
+            DecodeError::InvalidLength { expected, actual } => write!(
+                f,
+                "invalid branch node length, expected {} actual {}",
+                expected, actual
+            ),
+            DecodeError::TrailingBytes { expected, actual } => write!(
+                f,
+                "trailing bytes after branch node, expected {} actual {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The set of `MergeValue` variants which may appear in the branch nodes of a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeValueSet {
+    /// `Value` and `MergeWithZero`, written by a default build.
+    Basic,
+    /// `Value`, `MergeWithZero` and `ShortCut`, written by a build with the `trie` feature.
+    Trie,
+}
+
+impl MergeValueSet {
+    /// The variant set of this build.
+    pub fn current() -> Self {
+        if cfg!(feature = "trie") {
+            MergeValueSet::Trie
+        } else {
+            MergeValueSet::Basic
+        }
+    }
+}
+
 /// Serialize a `BranchKey` into a `Vec<u8>` for use as a key in the key-value store.
 pub fn branch_key_to_vec(key: &BranchKey) -> Vec<u8> {
     let mut ret = Vec::with_capacity(33);
@@ -10,15 +86,21 @@ pub fn branch_key_to_vec(key: &BranchKey) -> Vec<u8> {
     ret
 }
 
-/// Serialize a `BranchNode` into a `Vec<u8>` for use as a key in the key-value store.
+/// Serialize a `BranchNode` into a `Vec<u8>` for use as a value in the key-value store.
 pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(MAX_BRANCH_NODE_LEN);
+    ret.push(VERSION_HEADER | BRANCH_NODE_FORMAT_VERSION);
+    write_tagged_branch_node(node, &mut ret);
+    ret
+}
+
+/// Append the tagged encoding of a `BranchNode`, shared by all format versions, to `ret`.
+fn write_tagged_branch_node(node: &BranchNode, ret: &mut Vec<u8>) {
     match (&node.left, &node.right) {
         (MergeValue::Value(left), MergeValue::Value(right)) => {
-            let mut ret = Vec::with_capacity(33);
             ret.extend_from_slice(&[0]);
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(right.as_slice());
-            ret
         }
         (
             MergeValue::Value(left),
@@ -28,13 +110,11 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
                 zero_count,
             },
         ) => {
-            let mut ret = Vec::with_capacity(98);
             ret.extend_from_slice(&[1]);
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
-            ret
         }
         (
             MergeValue::MergeWithZero {
@@ -44,13 +124,11 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             },
             MergeValue::Value(right),
         ) => {
-            let mut ret = Vec::with_capacity(98);
             ret.extend_from_slice(&[2]);
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
             ret.extend_from_slice(right.as_slice());
-            ret
         }
         (
             MergeValue::MergeWithZero {
@@ -64,7 +142,6 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
                 zero_count: r_zero_count,
             },
         ) => {
-            let mut ret = Vec::with_capacity(131);
             ret.extend_from_slice(&[3]);
             ret.extend_from_slice(l_base_node.as_slice());
             ret.extend_from_slice(l_zero_bits.as_slice());
@@ -72,27 +149,22 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             ret.extend_from_slice(r_base_node.as_slice());
             ret.extend_from_slice(r_zero_bits.as_slice());
             ret.extend_from_slice(&[*r_zero_count]);
-            ret
         }
         #[cfg(feature = "trie")]
         (MergeValue::Value(left), MergeValue::ShortCut { key, value, height }) => {
-            let mut ret = Vec::with_capacity(98);
             ret.extend_from_slice(&[4]);
             ret.extend_from_slice(left.as_slice());
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
-            ret
         }
         #[cfg(feature = "trie")]
         (MergeValue::ShortCut { key, value, height }, MergeValue::Value(right)) => {
-            let mut ret = Vec::with_capacity(98);
             ret.extend_from_slice(&[5]);
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
             ret.extend_from_slice(right.as_slice());
-            ret
         }
         #[cfg(feature = "trie")]
         (
@@ -107,7 +179,6 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
                 height: r_height,
             },
         ) => {
-            let mut ret = Vec::with_capacity(131);
             ret.extend_from_slice(&[6]);
             ret.extend_from_slice(l_key.as_slice());
             ret.extend_from_slice(l_value.as_slice());
@@ -115,7 +186,6 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             ret.extend_from_slice(r_key.as_slice());
             ret.extend_from_slice(r_value.as_slice());
             ret.extend_from_slice(&[*r_height]);
-            ret
         }
         #[cfg(feature = "trie")]
         (
@@ -126,7 +196,6 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             },
             MergeValue::ShortCut { key, value, height },
         ) => {
-            let mut ret = Vec::with_capacity(131);
             ret.extend_from_slice(&[7]);
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
@@ -134,7 +203,6 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
             ret.extend_from_slice(&[*height]);
-            ret
         }
         #[cfg(feature = "trie")]
         (
@@ -145,7 +213,6 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
                 zero_count,
             },
         ) => {
-            let mut ret = Vec::with_capacity(131);
             ret.extend_from_slice(&[8]);
             ret.extend_from_slice(key.as_slice());
             ret.extend_from_slice(value.as_slice());
@@ -153,14 +220,96 @@ pub fn branch_node_to_vec(node: &BranchNode) -> Vec<u8> {
             ret.extend_from_slice(base_node.as_slice());
             ret.extend_from_slice(zero_bits.as_slice());
             ret.extend_from_slice(&[*zero_count]);
-            ret
         }
     }
 }
 
+/// Returns the length of a serialized `BranchNode` with the given tag.
+fn branch_node_len(tag: u8) -> Result<usize, DecodeError> {
+    match tag {
+        0 => Ok(65),
+        1 | 2 => Ok(98),
+        3 => Ok(131),
+        #[cfg(feature = "trie")]
+        4 | 5 => Ok(98),
+        #[cfg(feature = "trie")]
+        6..=8 => Ok(131),
+        #[cfg(not(feature = "trie"))]
+        4..=8 => Err(DecodeError::TrieNotEnabled(tag)),
+        _ => Err(DecodeError::UnknownTag(tag)),
+    }
+}
+
+/// Returns the format version of a serialized `BranchNode`.
+pub fn branch_node_version(slice: &[u8]) -> Result<u8, DecodeError> {
+    match slice.first() {
+        Some(header) if header & VERSION_HEADER != 0 => Ok(header & !VERSION_HEADER),
+        Some(_) => Ok(BRANCH_NODE_FORMAT_V1),
+        None => Err(DecodeError::InvalidLength {
+            expected: 1,
+            actual: 0,
+        }),
+    }
+}
+
+/// Returns the `MergeValueSet` required to decode a serialized `BranchNode`.
+pub fn branch_node_merge_value_set(slice: &[u8]) -> Result<MergeValueSet, DecodeError> {
+    let offset = match branch_node_version(slice)? {
+        BRANCH_NODE_FORMAT_V1 => 0,
+        BRANCH_NODE_FORMAT_VERSION => 1,
+        version => return Err(DecodeError::UnsupportedVersion(version)),
+    };
+    match slice.get(offset) {
+        Some(0..=3) => Ok(MergeValueSet::Basic),
+        Some(4..=8) => Ok(MergeValueSet::Trie),
+        Some(tag) => Err(DecodeError::UnknownTag(*tag)),
+        None => Err(DecodeError::InvalidLength {
+            expected: offset + 1,
+            actual: slice.len(),
+        }),
+    }
+}
+
 /// Deserialize a `BranchNode` from a slice that was previously serialized with `branch_node_to_vec`.
-pub fn slice_to_branch_node(slice: &[u8]) -> BranchNode {
-    match slice[0] {
+pub fn slice_to_branch_node(slice: &[u8]) -> Result<BranchNode, DecodeError> {
+    match branch_node_version(slice)? {
+        BRANCH_NODE_FORMAT_VERSION => slice_to_tagged_branch_node(slice, 1),
+        version => Err(DecodeError::UnsupportedVersion(version)),
+    }
+}
+
+/// Rewrite a serialized `BranchNode` of any older format version into the current one.
+pub fn upgrade_branch_node(slice: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let node = match branch_node_version(slice)? {
+        BRANCH_NODE_FORMAT_V1 => slice_to_tagged_branch_node(slice, 0)?,
+        BRANCH_NODE_FORMAT_VERSION => slice_to_tagged_branch_node(slice, 1)?,
+        version => return Err(DecodeError::UnsupportedVersion(version)),
+    };
+    Ok(branch_node_to_vec(&node))
+}
+
+/// Deserialize a `BranchNode` from its tagged encoding, which starts after `offset` header bytes.
+fn slice_to_tagged_branch_node(slice: &[u8], offset: usize) -> Result<BranchNode, DecodeError> {
+    let tag = *slice.get(offset).ok_or(DecodeError::InvalidLength {
+        expected: offset + 1,
+        actual: slice.len(),
+    })?;
+    let expected = offset + branch_node_len(tag)?;
+    if slice.len() < expected {
+        return Err(DecodeError::InvalidLength {
+            expected,
+            actual: slice.len(),
+        });
+    }
+    if slice.len() > expected {
+        return Err(DecodeError::TrailingBytes {
+            expected,
+            actual: slice.len(),
+        });
+    }
+
+    let slice = &slice[offset..];
+    let node = match tag {
         0 => {
             let left: [u8; 32] = slice[1..33].try_into().expect("checked slice");
             let right: [u8; 32] = slice[33..65].try_into().expect("checked slice");
@@ -310,8 +459,7 @@ pub fn slice_to_branch_node(slice: &[u8]) -> BranchNode {
                 },
             }
         }
-        _ => {
-            unreachable!()
-        }
-    }
+        _ => return Err(DecodeError::UnknownTag(tag)),
+    };
+    Ok(node)
 }
@@ -0,0 +1,87 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, Open, OpenCF},
+    OptimisticTransactionDB, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, BranchKey, SparseMerkleTree, H256};
+
+use crate::{
+    batch_read::{merkle_proof, StoreBatchReadOps},
+    cf_store::ColumnFamilyStore,
+    default_store::DefaultStoreMultiTree,
+};
+
+use super::{kvs, Word};
+
+fn check_batch_reads<S: StoreBatchReadOps<Word>>(smt: &SparseMerkleTree<Blake2bHasher, Word, S>) {
+    let keys: Vec<H256> = kvs().into_iter().map(|(key, _)| key).collect();
+    let leaves = smt.store().get_leaves(&keys).unwrap();
+    for (key, leaf) in keys.iter().zip(leaves) {
+        let expected: Option<Word> = smt.store().get_leaf(key).unwrap();
+        assert_eq!(leaf.map(|l| l.0), expected.map(|l| l.0));
+    }
+
+    let branch_keys: Vec<BranchKey> = keys
+        .iter()
+        .flat_map(|key| [0, 128, 255].map(|height| BranchKey::new(height, key.parent_path(height))))
+        .chain([BranchKey::new(0, H256::zero())])
+        .collect();
+    let branches = smt.store().get_branches(&branch_keys).unwrap();
+    assert_eq!(branches.len(), branch_keys.len());
+    for (key, branch) in branch_keys.iter().zip(branches) {
+        assert_eq!(branch, smt.store().get_branch(key).unwrap());
+    }
+
+    let mut missing_key = [0u8; 32];
+    missing_key[0] = 42;
+    for keys in [
+        vec![keys[0]],
+        keys[1..5].to_vec(),
+        vec![keys[3], missing_key.into()],
+        keys.clone(),
+    ] {
+        assert_eq!(
+            merkle_proof(smt, keys.clone()).unwrap(),
+            smt.merkle_proof(keys).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_batch_reads_of_default_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    let store = DefaultStoreMultiTree::<_, WriteOptions>::new(b"tree1.", &db);
+    let mut smt = SparseMerkleTree::<Blake2bHasher, Word, _>::new_with_store(store).unwrap();
+    smt.update_all(kvs().into_iter().skip(1).collect()).unwrap();
+    check_batch_reads(&smt);
+}
+
+#[test]
+fn test_batch_reads_of_cf_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    let store = ColumnFamilyStore::<_, WriteOptions>::new(&db, branch_col, leaf_col);
+    let mut smt = SparseMerkleTree::<Blake2bHasher, Word, _>::new_with_store(store).unwrap();
+    smt.update_all(kvs().into_iter().skip(1).collect()).unwrap();
+    check_batch_reads(&smt);
+}
+
+#[test]
+fn test_batch_reads_of_transaction() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+    let tx = db.transaction_default();
+
+    // the batched reads see the uncommitted writes of the transaction
+    let store = DefaultStoreMultiTree::<_, ()>::new(b"tree1.", &tx);
+    let mut smt = SparseMerkleTree::<Blake2bHasher, Word, _>::new_with_store(store).unwrap();
+    smt.update_all(kvs().into_iter().skip(1).collect()).unwrap();
+    check_batch_reads(&smt);
+}
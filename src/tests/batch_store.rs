@@ -0,0 +1,109 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, Iterate, Open, OpenCF},
+    IteratorMode, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree};
+
+use crate::{
+    batch_store::{commit_tree, open_tree, WriteBatchStore},
+    cf_store::ColumnFamilyStore,
+    default_store::DefaultStore,
+    format::{read_format, StoreFormat},
+    root::{self, read_root},
+};
+
+use super::{kvs, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type BatchStoreSMT<S> = SparseMerkleTree<Blake2bHasher, Word, WriteBatchStore<S, Word>>;
+
+#[test]
+fn test_commit_batch() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    let mut smt: BatchStoreSMT<_> =
+        open_tree(DefaultStore::<_, WriteOptions>::open(&db).unwrap()).unwrap();
+    smt.update_all(kvs()).unwrap();
+    assert!(!smt.store().is_empty());
+    // pending writes are visible to the tree, but not written to the database
+    let (key, value) = kvs()[0].clone();
+    let proof = smt.merkle_proof(vec![key]).unwrap();
+    assert!(proof
+        .verify::<Blake2bHasher>(smt.root(), vec![(key, value.to_h256())])
+        .unwrap());
+    assert_eq!(db.iterator(IteratorMode::Start).count(), 0);
+
+    commit_tree(&mut smt).unwrap();
+    assert!(smt.store().is_empty());
+    let root = *smt.root();
+
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert_eq!(read_root(&store), Ok(Some(root)));
+    assert_eq!(read_format(&store), Ok(Some(StoreFormat::current())));
+    let mut expected = DefaultStoreSMT::<_, WriteOptions>::new_with_store(store).unwrap();
+    assert_eq!(*expected.root(), root);
+
+    // the tree keeps working after a commit, removed leaves and branches are deleted on the next commit
+    for (key, _) in kvs().into_iter().skip(2) {
+        smt.update(key, Word::default()).unwrap();
+        expected.update(key, Word::default()).unwrap();
+    }
+    commit_tree(&mut smt).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    let smt: DefaultStoreSMT<_, _> =
+        root::open_tree(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    assert_eq!(smt.root(), expected.root());
+}
+
+#[test]
+fn test_discard_batch() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    let mut smt: BatchStoreSMT<_> = open_tree(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    smt.update_all(kvs().into_iter().take(3).collect()).unwrap();
+    commit_tree(&mut smt).unwrap();
+    let committed_root = *smt.root();
+
+    smt.update_all(kvs().into_iter().skip(3).collect()).unwrap();
+    smt.store_mut().discard();
+    assert!(smt.store().is_empty());
+    drop(smt);
+
+    let mut smt: BatchStoreSMT<_> = open_tree(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    assert_eq!(*smt.root(), committed_root);
+    smt.update_all(kvs().into_iter().skip(3).collect()).unwrap();
+    drop(smt);
+
+    let smt: BatchStoreSMT<_> = open_tree(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    assert_eq!(*smt.root(), committed_root);
+}
+
+#[test]
+fn test_commit_batch_to_column_families() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    let mut smt: BatchStoreSMT<_> = open_tree(ColumnFamilyStore::<_, WriteOptions>::new(
+        &db, branch_col, leaf_col,
+    ))
+    .unwrap();
+    smt.update_all(kvs()).unwrap();
+    commit_tree(&mut smt).unwrap();
+
+    assert_eq!(db.iterator(IteratorMode::Start).count(), 0);
+    let store = ColumnFamilyStore::<_, WriteOptions>::new(&db, branch_col, leaf_col);
+    assert_eq!(read_root(&store), Ok(Some(*smt.root())));
+    let (key, value) = kvs()[0].clone();
+    let reopened: SparseMerkleTree<Blake2bHasher, Word, _> = root::open_tree(store).unwrap();
+    let proof = reopened.merkle_proof(vec![key]).unwrap();
+    assert!(proof
+        .verify::<Blake2bHasher>(smt.root(), vec![(key, value.to_h256())])
+        .unwrap());
+}
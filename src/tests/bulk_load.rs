@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use rocksdb::{
+    prelude::{GetColumnFamilys, Open, OpenCF},
+    Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, traits::Value, BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    bulk_load::BulkLoader,
+    cf_store::ColumnFamilyStore,
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    iter::StoreIterOps,
+    namespace::Namespace,
+    root::{open_tree, read_root},
+    verify::verify_store,
+};
+
+use super::{expected_tree, kvs, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+
+// The leaves of `kvs` sorted in the order of the tree, with a zero leaf.
+fn sorted_kvs() -> Vec<(H256, Word)> {
+    let mut kvs = kvs();
+    kvs.sort_by_key(|(key, _)| *key);
+    kvs[7].1 = Word::default();
+    kvs
+}
+
+fn branches<S: StoreIterOps>(store: &S) -> HashMap<BranchKey, BranchNode> {
+    store
+        .branches(None)
+        .unwrap()
+        .map(|branch| branch.unwrap())
+        .collect()
+}
+
+#[test]
+fn test_bulk_load() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let sst_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let kvs = sorted_kvs();
+    let expected = expected_tree(std::slice::from_ref(&kvs));
+
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    let root = BulkLoader::new(&store, sst_dir.path())
+        .with_records_per_file(1000)
+        .load::<Blake2bHasher, _, _>(kvs.clone())
+        .unwrap();
+    assert_eq!(root, *expected.root());
+    assert_eq!(read_root(&store), Ok(Some(root)));
+    assert_eq!(branches(&store), *expected.store().branches_map());
+    assert_eq!(
+        store
+            .leaves::<Word>(None, rocksdb::Direction::Forward)
+            .unwrap()
+            .count(),
+        kvs.len() - 1
+    );
+    assert!(verify_store::<Blake2bHasher, Word, _>(&store, &root)
+        .unwrap()
+        .is_ok());
+    // the files were moved into the database
+    assert_eq!(std::fs::read_dir(sst_dir.path()).unwrap().count(), 0);
+
+    // the loaded tree can be updated
+    let mut smt: DefaultStoreSMT<_, WriteOptions> = open_tree(store).unwrap();
+    let (key, value) = kvs[0].clone();
+    let proof = smt.merkle_proof(vec![key]).unwrap();
+    assert!(proof
+        .verify::<Blake2bHasher>(&root, vec![(key, value.to_h256())])
+        .unwrap());
+    smt.update(key, Word::default()).unwrap();
+    let mut expected = expected;
+    expected.update(key, Word::default()).unwrap();
+    assert_eq!(smt.root(), expected.root());
+
+    // only into an empty store
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert!(BulkLoader::new(&store, sst_dir.path())
+        .load::<Blake2bHasher, _, _>(kvs[..1].to_vec())
+        .is_err());
+}
+
+#[test]
+fn test_bulk_load_column_families() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let sst_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let store = ColumnFamilyStore::<_, WriteOptions>::new(
+        &db,
+        db.cf_handle("cf1").unwrap(),
+        db.cf_handle("cf2").unwrap(),
+    );
+    let kvs = sorted_kvs();
+    let expected = expected_tree(std::slice::from_ref(&kvs));
+
+    let root = BulkLoader::new(&store, sst_dir.path())
+        .with_records_per_file(10)
+        .load::<Blake2bHasher, _, _>(kvs)
+        .unwrap();
+    assert_eq!(root, *expected.root());
+    assert_eq!(read_root(&store), Ok(Some(root)));
+    assert_eq!(branches(&store), *expected.store().branches_map());
+    assert!(verify_store::<Blake2bHasher, Word, _>(&store, &root)
+        .unwrap()
+        .is_ok());
+
+    // an empty tree
+    let namespace = Namespace::new(b"empty").unwrap();
+    let store = DefaultStoreMultiTree::<_, WriteOptions>::with_namespace(&namespace, &db);
+    assert_eq!(
+        BulkLoader::new(&store, sst_dir.path()).load::<Blake2bHasher, Word, _>(Vec::new()),
+        Ok(H256::zero())
+    );
+    assert_eq!(read_root(&store), Ok(Some(H256::zero())));
+}
+
+#[test]
+fn test_bulk_load_unsorted_leaves() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let sst_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    let mut kvs = sorted_kvs();
+    kvs.swap(2, 3);
+
+    assert!(BulkLoader::new(&store, sst_dir.path())
+        .with_records_per_file(10)
+        .load::<Blake2bHasher, _, _>(kvs)
+        .is_err());
+    assert_eq!(read_root(&store), Ok(None));
+    assert_eq!(
+        store
+            .leaves::<Word>(None, rocksdb::Direction::Forward)
+            .unwrap()
+            .count(),
+        0
+    );
+    assert_eq!(std::fs::read_dir(sst_dir.path()).unwrap().count(), 0);
+}
@@ -0,0 +1,192 @@
+use std::{cell::RefCell, sync::Arc};
+
+use rocksdb::{prelude::Open, ReadOptions, WriteOptions, DB};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    error::Error,
+    merge::MergeValue,
+    traits::{StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::{Backend, KeySpace},
+    cached_store::{BranchCache, CacheStats, CachedStore},
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type CachedStoreSMT<S> = SparseMerkleTree<Blake2bHasher, Word, CachedStore<S>>;
+
+#[test]
+fn test_cache_hits_and_invalidation() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let cache = Arc::new(BranchCache::new(1024));
+
+    let mut smt: CachedStoreSMT<_> = SparseMerkleTree::new_with_store(CachedStore::new(
+        DefaultStore::<_, WriteOptions>::new(&db),
+        cache.clone(),
+    ))
+    .unwrap();
+    let mut expected = MemoryStoreSMT::default();
+    for (key, value) in kvs() {
+        smt.update(key, value.clone()).unwrap();
+        expected.update(key, value).unwrap();
+        assert_eq!(smt.root(), expected.root());
+    }
+    for (key, _) in kvs().into_iter().step_by(2) {
+        smt.update(key, Word::default()).unwrap();
+        expected.update(key, Word::default()).unwrap();
+        assert_eq!(smt.root(), expected.root());
+    }
+
+    cache.clear();
+    cache.reset_stats();
+    let root_key = BranchKey::new(u8::MAX, H256::zero());
+    let root_branch = StoreReadOps::<Word>::get_branch(smt.store(), &root_key).unwrap();
+    assert!(root_branch.is_some());
+    assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 1 });
+    assert_eq!(
+        StoreReadOps::<Word>::get_branch(smt.store(), &root_key).unwrap(),
+        root_branch
+    );
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+    let (key, value) = kvs()[1].clone();
+    let proof = smt.merkle_proof(vec![key]).unwrap();
+    assert!(proof
+        .verify::<Blake2bHasher>(expected.root(), vec![(key, value.to_h256())])
+        .unwrap());
+    assert!(cache.stats().hits > 1);
+}
+
+#[test]
+fn test_cache_capacity() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let cache = Arc::new(BranchCache::new(4));
+
+    let mut smt: CachedStoreSMT<_> = SparseMerkleTree::new_with_store(CachedStore::new(
+        DefaultStore::<_, WriteOptions>::new(&db),
+        cache.clone(),
+    ))
+    .unwrap();
+    smt.update_all(kvs()).unwrap();
+    for (key, _) in kvs() {
+        smt.merkle_proof(vec![key]).unwrap();
+        assert!(cache.len() <= cache.capacity());
+    }
+    assert_eq!(cache.len(), 4);
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_shared_cache_of_multiple_trees() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let cache = Arc::new(BranchCache::new(1024));
+
+    let mut roots = Vec::new();
+    for (i, prefix) in [b"tree1.", b"tree2."].into_iter().enumerate() {
+        let mut smt: CachedStoreSMT<_> = SparseMerkleTree::new_with_store(CachedStore::new(
+            DefaultStoreMultiTree::<_, WriteOptions>::new(prefix, &db),
+            cache.clone(),
+        ))
+        .unwrap();
+        smt.update_all(kvs().into_iter().skip(i).collect()).unwrap();
+        smt.merkle_proof(vec![kvs()[2].0]).unwrap();
+        roots.push(*smt.root());
+    }
+    assert_ne!(roots[0], roots[1]);
+
+    for (prefix, root) in [b"tree1.", b"tree2."].into_iter().zip(roots) {
+        let smt: CachedStoreSMT<_> = SparseMerkleTree::new_with_store(CachedStore::new(
+            DefaultStoreMultiTree::<_, WriteOptions>::new(prefix, &db),
+            cache.clone(),
+        ))
+        .unwrap();
+        assert_eq!(*smt.root(), root);
+    }
+}
+
+type TestStore<'a> = DefaultStore<'a, DB, WriteOptions>;
+
+// A store whose first read of a branch is overtaken by a write of the branch through another store.
+struct RacingStore<'a> {
+    inner: TestStore<'a>,
+    writer: RefCell<Option<(CachedStore<TestStore<'a>>, BranchNode)>>,
+}
+
+impl<'a> Backend for RacingStore<'a> {
+    type DB = DB;
+    type WriteOptions = WriteOptions;
+
+    fn db(&self) -> &DB {
+        self.inner.db()
+    }
+
+    fn read_options(&self) -> Option<&ReadOptions> {
+        self.inner.read_options()
+    }
+
+    fn write_options(&self) -> Option<&WriteOptions> {
+        self.inner.write_options()
+    }
+
+    fn branch_space(&self) -> KeySpace<'_> {
+        self.inner.branch_space()
+    }
+
+    fn leaf_space(&self) -> KeySpace<'_> {
+        self.inner.leaf_space()
+    }
+}
+
+impl<'a> StoreReadOps<Word> for RacingStore<'a> {
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        let branch = StoreReadOps::<Word>::get_branch(&self.inner, branch_key)?;
+        if let Some((mut writer, new_branch)) = self.writer.borrow_mut().take() {
+            StoreWriteOps::<Word>::insert_branch(&mut writer, branch_key.clone(), new_branch)?;
+        }
+        Ok(branch)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<Word>, Error> {
+        self.inner.get_leaf(leaf_key)
+    }
+}
+
+#[test]
+fn test_concurrent_write_is_not_cached_stale() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let cache = Arc::new(BranchCache::new(1024));
+    let branch_key = BranchKey::new(0, H256::zero());
+    let branch = |byte: u8| BranchNode {
+        left: MergeValue::from_h256([byte; 32].into()),
+        right: MergeValue::zero(),
+    };
+    let mut store = DefaultStore::<_, WriteOptions>::new(&db);
+    StoreWriteOps::<Word>::insert_branch(&mut store, branch_key.clone(), branch(1)).unwrap();
+
+    let writer = CachedStore::new(DefaultStore::new(&db), cache.clone());
+    let reader = CachedStore::new(
+        RacingStore {
+            inner: DefaultStore::new(&db),
+            writer: RefCell::new(Some((writer, branch(2)))),
+        },
+        cache,
+    );
+    // the read returns the branch written before it, which is not cached
+    assert_eq!(
+        StoreReadOps::<Word>::get_branch(&reader, &branch_key),
+        Ok(Some(branch(1)))
+    );
+    assert_eq!(
+        StoreReadOps::<Word>::get_branch(&reader, &branch_key),
+        Ok(Some(branch(2)))
+    );
+}
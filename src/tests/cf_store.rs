@@ -1,17 +1,23 @@
+use std::{sync::Arc, thread};
+
 use rocksdb::{
     prelude::{GetColumnFamilys, OpenCF},
     OptimisticTransactionDB, Options, DB,
 };
 use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree, H256};
 
-use crate::cf_store::{ColumnFamilyStore, ColumnFamilyStoreMultiTree};
+use crate::cf_store::{
+    ColumnFamilyStore, ColumnFamilyStoreMultiTree, OwnedColumnFamilyStore,
+    OwnedColumnFamilyStoreMultiTree,
+};
 
-use super::{new_blake2b, MemoryStoreSMT, Word};
+use super::{kvs, new_blake2b, MemoryStoreSMT, Word};
 
 type ColumnFamilyStoreSMT<'a, T, W> =
     SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
 
 #[test]
+#[allow(clippy::clone_on_copy)]
 fn test_store_functions() {
     let kvs = "The quick brown fox jumps over the lazy dog"
         .split_whitespace()
@@ -107,6 +113,7 @@ type ColumnFamilyStoreMultiSMT<'a, T, W> =
     SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStoreMultiTree<'a, T, W>>;
 
 #[test]
+#[allow(clippy::clone_on_copy, clippy::needless_borrow)]
 fn test_multi_trees_store_functions() {
     let kvs = "The quick brown fox jumps over the lazy dog"
         .split_whitespace()
@@ -170,6 +177,7 @@ fn test_multi_trees_store_functions() {
 }
 
 #[test]
+#[allow(clippy::clone_on_copy, clippy::needless_borrow)]
 fn test_rw_function() {
     let kvs = "The quick brown fox jumps over the lazy dog"
         .split_whitespace()
@@ -219,3 +227,66 @@ fn test_rw_function() {
     let leaf_value2 = recovered_smt2.get(&kvs[0].0).unwrap();
     assert_eq!(leaf_value2.0, "".to_string());
 }
+
+type OwnedColumnFamilyStoreSMT<T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, OwnedColumnFamilyStore<T, W>>;
+type OwnedColumnFamilyStoreMultiSMT<T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, OwnedColumnFamilyStoreMultiTree<T, W>>;
+
+#[test]
+fn test_owned_store_functions() {
+    let kvs = kvs();
+
+    let root1 = {
+        let mut memory_store_smt = MemoryStoreSMT::new_with_store(Default::default()).unwrap();
+        memory_store_smt.update_all(kvs.clone()).unwrap();
+        *memory_store_smt.root()
+    };
+
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = Arc::new(
+        OptimisticTransactionDB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap(),
+    );
+
+    assert!(OwnedColumnFamilyStore::<_, ()>::new(db.clone(), "cf1", "cf3").is_err());
+
+    // the owned stores can be moved into other threads
+    let handles = vec![
+        {
+            let mut smt = OwnedColumnFamilyStoreSMT::<_, _>::new_with_store(
+                OwnedColumnFamilyStore::new(db.clone(), "cf1", "cf2").unwrap(),
+            )
+            .unwrap();
+            let kvs = kvs.clone();
+            thread::spawn(move || *smt.update_all(kvs).unwrap())
+        },
+        {
+            let mut smt = OwnedColumnFamilyStoreMultiSMT::<_, _>::new_with_store(
+                OwnedColumnFamilyStoreMultiTree::new(b"tree1", db.clone(), "cf1", "cf2").unwrap(),
+            )
+            .unwrap();
+            let kvs = kvs.clone();
+            thread::spawn(move || *smt.update_all(kvs).unwrap())
+        },
+    ];
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), root1);
+    }
+
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+    let snapshot = db.snapshot();
+    let smt = ColumnFamilyStoreSMT::new_with_store(ColumnFamilyStore::<_, ()>::new(
+        &snapshot, branch_col, leaf_col,
+    ))
+    .unwrap();
+    assert_eq!(*smt.root(), root1);
+    let smt = ColumnFamilyStoreMultiSMT::new_with_store(ColumnFamilyStoreMultiTree::<_, ()>::new(
+        b"tree1", &snapshot, branch_col, leaf_col,
+    ))
+    .unwrap();
+    assert_eq!(*smt.root(), root1);
+}
@@ -1,13 +1,26 @@
-use rocksdb::{prelude::Open, OptimisticTransactionDB, DB};
-use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree, H256};
+use std::{sync::Arc, thread};
 
-use crate::default_store::{DefaultStore, DefaultStoreMultiTree};
+use rocksdb::{
+    prelude::{Delete, Get, Iterate, Open, Put},
+    Direction, IteratorMode, OptimisticTransactionDB, ReadOptions, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, BranchKey, SparseMerkleTree, H256};
 
-use super::{new_blake2b, MemoryStoreSMT, Word};
+use crate::{
+    default_store::{
+        convert_to_tagged, DefaultStore, DefaultStoreMultiTree, KeyLayout, OwnedDefaultStore,
+        OwnedDefaultStoreMultiTree, BRANCH_KEY_TAG, LEAF_KEY_TAG,
+    },
+    root::{commit_root, read_root},
+    serde::branch_key_to_vec,
+};
+
+use super::{kvs, new_blake2b, MemoryStoreSMT, Word};
 
 type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
 
 #[test]
+#[allow(clippy::clone_on_copy)]
 fn test_store_functions() {
     let kvs = "The quick brown fox jumps over the lazy dog"
         .split_whitespace()
@@ -88,6 +101,7 @@ type DefaultStoreMultiSMT<'a, T, W> =
     SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
 
 #[test]
+#[allow(clippy::clone_on_copy)]
 fn test_multi_trees_store_functions() {
     let kvs = "The quick brown fox jumps over the lazy dog"
         .split_whitespace()
@@ -143,3 +157,179 @@ fn test_multi_trees_store_functions() {
         assert_ne!(root_tree1, root_tree2);
     };
 }
+
+type OwnedDefaultStoreSMT<T, W> = SparseMerkleTree<Blake2bHasher, Word, OwnedDefaultStore<T, W>>;
+type OwnedDefaultStoreMultiSMT<T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, OwnedDefaultStoreMultiTree<T, W>>;
+
+#[test]
+fn test_owned_store_functions() {
+    let kvs = kvs();
+
+    let root1 = {
+        let mut memory_store_smt = MemoryStoreSMT::new_with_store(Default::default()).unwrap();
+        memory_store_smt.update_all(kvs.clone()).unwrap();
+        *memory_store_smt.root()
+    };
+
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = Arc::new(DB::open_default(tmp_dir.path()).unwrap());
+
+    // the owned stores can be moved into other threads
+    let handles = vec![
+        {
+            let mut smt = OwnedDefaultStoreSMT::<_, WriteOptions>::new_with_store(
+                OwnedDefaultStore::new(db.clone()),
+            )
+            .unwrap();
+            let kvs = kvs.clone();
+            thread::spawn(move || *smt.update_all(kvs).unwrap())
+        },
+        {
+            let mut smt = OwnedDefaultStoreMultiSMT::<_, WriteOptions>::new_with_store(
+                OwnedDefaultStoreMultiTree::new(b"tree1", db.clone()),
+            )
+            .unwrap();
+            let kvs = kvs.clone();
+            thread::spawn(move || *smt.update_all(kvs).unwrap())
+        },
+    ];
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), root1);
+    }
+
+    let smt =
+        DefaultStoreSMT::new_with_store(DefaultStore::<_, WriteOptions>::new(db.as_ref())).unwrap();
+    assert_eq!(*smt.root(), root1);
+    let smt = DefaultStoreMultiSMT::new_with_store(DefaultStoreMultiTree::<_, WriteOptions>::new(
+        b"tree1",
+        db.as_ref(),
+    ))
+    .unwrap();
+    assert_eq!(*smt.root(), root1);
+}
+
+#[test]
+fn test_store_with_options() {
+    let kvs = kvs();
+
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = Arc::new(DB::open_default(tmp_dir.path()).unwrap());
+    let mut write_options = WriteOptions::default();
+    write_options.set_sync(true);
+    write_options.disable_wal(false);
+
+    let mut smt = DefaultStoreMultiSMT::new_with_store(
+        DefaultStoreMultiTree::new(b"tree1", db.as_ref()).with_write_options(&write_options),
+    )
+    .unwrap();
+    smt.update_all(kvs[..4].to_vec()).unwrap();
+    let root1 = *smt.root();
+
+    // reads through a snapshot set in the read options do not see later writes
+    let snapshot = db.snapshot();
+    let mut read_options = ReadOptions::default();
+    read_options.fill_cache(false);
+    read_options.set_snapshot(&snapshot);
+    smt.update_all(kvs[4..].to_vec()).unwrap();
+    assert_ne!(*smt.root(), root1);
+
+    let smt = DefaultStoreMultiSMT::new_with_store(
+        DefaultStoreMultiTree::<_, WriteOptions>::new(b"tree1", db.as_ref())
+            .with_read_options(&read_options),
+    )
+    .unwrap();
+    assert_eq!(*smt.root(), root1);
+    assert_eq!(smt.get(&kvs[4].0).unwrap().0, "");
+
+    let mut read_options = ReadOptions::default();
+    read_options.set_readahead_size(4096);
+    let smt = OwnedDefaultStoreMultiSMT::new_with_store(
+        OwnedDefaultStoreMultiTree::<_, WriteOptions>::new(b"tree1", db.clone())
+            .with_read_options(read_options),
+    )
+    .unwrap();
+    assert_eq!(smt.get(&kvs[4].0).unwrap().0, kvs[4].1 .0);
+}
+
+fn leaves_with_zero_key() -> Vec<(H256, Word)> {
+    // the tagged key of the zero leaf is the plain key of the root branch
+    let mut kvs = vec![(H256::zero(), Word("zero".to_string()))];
+    kvs.extend(super::kvs());
+    kvs
+}
+
+#[test]
+fn test_tagged_store_functions() {
+    let kvs = leaves_with_zero_key();
+    let mut expected = MemoryStoreSMT::default();
+    expected.update_all(kvs.clone()).unwrap();
+
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = Arc::new(DB::open_default(tmp_dir.path()).unwrap());
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new_with_store(DefaultStore::tagged(db.as_ref())).unwrap();
+    assert_eq!(smt.store().key_layout(), KeyLayout::Tagged);
+    smt.update_all(kvs.clone()).unwrap();
+    commit_root(&smt).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    assert_eq!(
+        smt.merkle_proof(vec![kvs[0].0]).unwrap(),
+        expected.merkle_proof(vec![kvs[0].0]).unwrap()
+    );
+
+    // the leaves and the branches are disjoint ranges of keys
+    for (key, _) in db.iterator(IteratorMode::Start) {
+        match key[0] {
+            BRANCH_KEY_TAG => assert_ne!(key.len(), 33),
+            LEAF_KEY_TAG => assert_eq!(key.len(), 33),
+            _ => panic!("untagged key {:?}", key),
+        }
+    }
+    let leaves: Vec<_> = db
+        .iterator(IteratorMode::From(&[LEAF_KEY_TAG], Direction::Forward))
+        .collect();
+    assert_eq!(leaves.len(), kvs.len());
+
+    let smt: OwnedDefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new_with_store(OwnedDefaultStore::open_tagged(db.clone()).unwrap())
+            .unwrap();
+    assert_eq!(smt.root(), expected.root());
+    assert_eq!(smt.get(&kvs[0].0).unwrap().0, "zero");
+    assert_eq!(read_root(smt.store()), Ok(Some(*expected.root())));
+}
+
+#[test]
+fn test_convert_to_tagged() {
+    let kvs = leaves_with_zero_key();
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new_with_store(DefaultStore::new(&db)).unwrap();
+    smt.update_all(kvs.clone()).unwrap();
+    commit_root(&smt).unwrap();
+    let root = *smt.root();
+    let records = db.iterator(IteratorMode::Start).count();
+
+    // an interrupted conversion, which rewrote the root branch and a leaf
+    let root_key = branch_key_to_vec(&BranchKey::new(u8::MAX, H256::zero()));
+    for (tag, key) in [
+        (BRANCH_KEY_TAG, root_key),
+        (LEAF_KEY_TAG, kvs[1].0.as_slice().to_vec()),
+    ] {
+        let value = db.get(&key).unwrap().unwrap();
+        db.put([&[tag][..], &key].concat(), &value).unwrap();
+        db.delete(&key).unwrap();
+    }
+    assert_eq!(convert_to_tagged(&db), Ok(records - 2));
+    assert_eq!(convert_to_tagged(&db), Ok(0));
+    assert_eq!(db.iterator(IteratorMode::Start).count(), records);
+
+    let smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new_with_store(DefaultStore::open_tagged(&db).unwrap()).unwrap();
+    assert_eq!(*smt.root(), root);
+    assert_eq!(read_root(smt.store()), Ok(Some(root)));
+    for (key, value) in kvs {
+        assert_eq!(smt.get(&key).unwrap().0, value.0);
+    }
+}
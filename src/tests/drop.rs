@@ -0,0 +1,166 @@
+use rocksdb::{
+    prelude::{Get, GetColumnFamilys, Iterate, IterateCF, Open, OpenCF, Put},
+    IteratorMode, OptimisticTransactionDB, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree, H256};
+
+use crate::{
+    cf_store::{ColumnFamilyStore, ColumnFamilyStoreMultiTree},
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    drop::{drop_tree, drop_unprefixed_tree},
+    namespace::Namespace,
+    root::{commit_root, open_tree, read_root, ROOT_KEY},
+};
+
+use super::{kvs, Word};
+
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
+type ColumnFamilyStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStoreMultiTree<'a, T, W>>;
+
+#[test]
+fn test_drop_tree() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    // namespaces, and raw prefixes which end right before each other or have no end key
+    let tree = Namespace::new(b"tree").unwrap();
+    let tree1 = Namespace::new(b"tree1").unwrap();
+    let prefixes: [&[u8]; 4] = [tree.as_bytes(), tree1.as_bytes(), b"\xfe", b"\xff\xff"];
+    let mut roots = Vec::new();
+    for prefix in prefixes {
+        let mut smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &db)).unwrap();
+        smt.update_all(kvs()).unwrap();
+        commit_root(&smt).unwrap();
+        roots.push(*smt.root());
+    }
+
+    for (i, prefix) in prefixes.iter().enumerate().skip(1).step_by(2) {
+        let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &db)).unwrap();
+        drop_tree(smt.store()).unwrap();
+        assert_eq!(read_root(smt.store()), Ok(Some(H256::zero())));
+        let keys: Vec<_> = db
+            .iterator(IteratorMode::Start)
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![[prefix, ROOT_KEY].concat().into_boxed_slice()]);
+        let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &db)).unwrap();
+        assert_eq!(*smt.root(), H256::zero());
+        assert_eq!(smt.get(&kvs()[0].0).unwrap().0, "");
+        roots[i] = H256::zero();
+    }
+    for (prefix, root) in prefixes.iter().zip(roots) {
+        let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &db)).unwrap();
+        assert_eq!(*smt.root(), root);
+        let expected =
+            SparseMerkleTree::<Blake2bHasher, Word, _>::new_with_store(smt.take_store()).unwrap();
+        assert_eq!(*expected.root(), root);
+    }
+}
+
+#[test]
+fn test_drop_tree_of_column_families() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = OptimisticTransactionDB::open_cf(
+        &options,
+        tmp_dir.path(),
+        vec!["cf1", "cf2", "cf3", "cf4"],
+    )
+    .unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+    let tree = Namespace::new(b"tree").unwrap();
+    let tree1 = Namespace::new(b"tree1").unwrap();
+
+    let tx = db.transaction_default();
+    for namespace in [&tree, &tree1] {
+        let mut smt: ColumnFamilyStoreMultiSMT<_, ()> = open_tree(
+            ColumnFamilyStoreMultiTree::with_namespace(namespace, &tx, branch_col, leaf_col),
+        )
+        .unwrap();
+        smt.update_all(kvs()).unwrap();
+        commit_root(&smt).unwrap();
+    }
+    tx.commit().unwrap();
+
+    // a transaction deletes the keys one by one
+    let tx = db.transaction_default();
+    let smt: ColumnFamilyStoreMultiSMT<_, ()> = open_tree(
+        ColumnFamilyStoreMultiTree::with_namespace(&tree, &tx, branch_col, leaf_col),
+    )
+    .unwrap();
+    drop_tree(smt.store()).unwrap();
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    for (namespace, leaves) in [(&tree, None), (&tree1, Some(kvs()))] {
+        let smt: ColumnFamilyStoreMultiSMT<_, ()> = open_tree(
+            ColumnFamilyStoreMultiTree::with_namespace(namespace, &snapshot, branch_col, leaf_col),
+        )
+        .unwrap();
+        match leaves {
+            Some(leaves) => assert_eq!(smt.get(&leaves[0].0).unwrap().0, leaves[0].1 .0),
+            None => assert_eq!(*smt.root(), H256::zero()),
+        }
+    }
+    assert_eq!(
+        db.iterator_cf(leaf_col, IteratorMode::Start)
+            .unwrap()
+            .filter(|(key, _)| key.starts_with(tree.as_bytes()))
+            .count(),
+        0
+    );
+
+    // the keys of a store without prefix are the whole column families, deleted with range deletes on the DB
+    let branch_col = db.cf_handle("cf3").unwrap();
+    let leaf_col = db.cf_handle("cf4").unwrap();
+    let mut smt: ColumnFamilyStoreSMT<_, WriteOptions> =
+        open_tree(ColumnFamilyStore::new(&db, branch_col, leaf_col)).unwrap();
+    smt.update_all(kvs()).unwrap();
+    drop_tree(smt.store()).unwrap();
+    let keys: Vec<_> = db
+        .iterator_cf(branch_col, IteratorMode::Start)
+        .unwrap()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(keys, vec![ROOT_KEY.to_vec().into_boxed_slice()]);
+    assert_eq!(
+        db.iterator_cf(leaf_col, IteratorMode::Start)
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_drop_unprefixed_tree() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: SparseMerkleTree<Blake2bHasher, Word, _> =
+        open_tree(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+    db.put(b"app", b"record").unwrap();
+
+    // the keys of a `DefaultStore` are the whole default column family
+    assert!(drop_tree(smt.store()).is_err());
+    assert_eq!(read_root(smt.store()), Ok(Some(*smt.root())));
+    assert!(db.get(b"app").unwrap().is_some());
+
+    drop_unprefixed_tree(smt.store()).unwrap();
+    let keys: Vec<_> = db
+        .iterator(IteratorMode::Start)
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(keys, vec![ROOT_KEY.to_vec().into_boxed_slice()]);
+}
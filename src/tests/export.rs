@@ -0,0 +1,181 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, Open, OpenCF},
+    Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree, H256};
+
+use crate::{
+    cf_store::ColumnFamilyStore,
+    default_store::DefaultStore,
+    export::{export_store, export_tree, Crc32, ExportHeader, EXPORT_FORMAT_VERSION},
+    format::StoreFormat,
+    iter::StoreIterOps,
+    root::commit_root,
+    serde::{branch_key_to_vec, branch_node_to_vec},
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type ColumnFamilyStoreSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
+
+type Records = Vec<(Vec<u8>, Vec<u8>)>;
+
+// Decode an export, checking its checksum, returns the header, the leaves and the branches.
+fn decode(export: &[u8]) -> (ExportHeader, Records, Records) {
+    let (body, checksum) = export.split_at(export.len() - 4);
+    let mut crc = Crc32::new();
+    crc.update(body);
+    assert_eq!(crc.finish().to_be_bytes(), checksum);
+
+    let header = ExportHeader::from_slice(&body[..ExportHeader::LEN]).unwrap();
+    let mut rest = &body[ExportHeader::LEN..];
+    let mut take = |key_len: usize| {
+        let (key, tail) = rest.split_at(key_len);
+        let len = u32::from_be_bytes(tail[..4].try_into().unwrap()) as usize;
+        let value = tail[4..4 + len].to_vec();
+        rest = &tail[4 + len..];
+        (key.to_vec(), value)
+    };
+    let leaves = (0..header.leaves).map(|_| take(32)).collect();
+    let branches = (0..header.branches.unwrap_or(0))
+        .map(|_| take(33))
+        .collect();
+    assert!(rest.is_empty());
+    (header, leaves, branches)
+}
+
+fn stored_leaves<S: StoreIterOps>(store: &S) -> Records {
+    store
+        .leaves::<Box<[u8]>>(None, rocksdb::Direction::Forward)
+        .unwrap()
+        .map(|(key, value)| (key.as_slice().to_vec(), value.to_vec()))
+        .collect()
+}
+
+#[test]
+fn test_crc32() {
+    let mut crc = Crc32::new();
+    crc.update(b"123456789");
+    assert_eq!(crc.finish(), 0xCBF4_3926);
+    assert_eq!(Crc32::new().finish(), 0);
+}
+
+#[test]
+fn test_export_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut expected = MemoryStoreSMT::default();
+    expected.update_all(kvs()).unwrap();
+
+    // an empty store
+    let mut export = Vec::new();
+    let header =
+        export_store::<Blake2bHasher, _, _>(&DefaultStore::<_, ()>::new(&db), &mut export, true)
+            .unwrap();
+    assert_eq!(header.root, H256::zero());
+    assert_eq!((header.leaves, header.branches), (0, Some(0)));
+    assert_eq!(export.len(), ExportHeader::LEN + 4);
+    assert_eq!(decode(&export).0, header);
+
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+
+    let mut export = Vec::new();
+    let header = export_store::<Blake2bHasher, _, _>(smt.store(), &mut export, true).unwrap();
+    assert_eq!(
+        header,
+        ExportHeader {
+            version: EXPORT_FORMAT_VERSION,
+            hasher_id: 1,
+            store_format: StoreFormat::current(),
+            root: *expected.root(),
+            leaves: kvs().len() as u64,
+            branches: Some(expected.store().branches_map().len() as u64),
+        }
+    );
+    let (decoded, leaves, branches) = decode(&export);
+    assert_eq!(decoded, header);
+    assert_eq!(leaves, stored_leaves(smt.store()));
+    let mut expected_branches: Records = expected
+        .store()
+        .branches_map()
+        .iter()
+        .map(|(key, node)| (branch_key_to_vec(key), branch_node_to_vec(node)))
+        .collect();
+    expected_branches.sort();
+    assert_eq!(branches, expected_branches);
+
+    // without the branches
+    let mut export = Vec::new();
+    let header = export_tree(&smt, &mut export, false).unwrap();
+    assert_eq!(header.root, *expected.root());
+    assert_eq!(header.branches, None);
+    let (decoded, leaves, branches) = decode(&export);
+    assert_eq!(decoded, header);
+    assert_eq!(leaves, stored_leaves(smt.store()));
+    assert!(branches.is_empty());
+
+    // the root of the root branch, if no root was committed
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs()).unwrap();
+    let header = export_store::<Blake2bHasher, _, _>(smt.store(), Vec::<u8>::new(), false).unwrap();
+    assert_eq!(header.root, *expected.root());
+}
+
+#[test]
+fn test_export_snapshot() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+    let mut smt: ColumnFamilyStoreSMT<_, WriteOptions> = SparseMerkleTree::new(
+        H256::zero(),
+        ColumnFamilyStore::new(&db, branch_col, leaf_col),
+    );
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+    let root = *smt.root();
+
+    let snapshot = db.snapshot();
+    let store = ColumnFamilyStore::<_, ()>::new(&snapshot, branch_col, leaf_col);
+    let leaves = stored_leaves(&store);
+    smt.update(kvs()[0].0, Word::default()).unwrap();
+    commit_root(&smt).unwrap();
+
+    let mut export = Vec::new();
+    let header = export_store::<Blake2bHasher, _, _>(&store, &mut export, true).unwrap();
+    assert_eq!(header.root, root);
+    let (_, exported, _) = decode(&export);
+    assert_eq!(exported, leaves);
+    assert_eq!(exported.len(), kvs().len());
+}
+
+#[test]
+fn test_export_header() {
+    let header = ExportHeader {
+        version: EXPORT_FORMAT_VERSION,
+        hasher_id: 1,
+        store_format: StoreFormat::current(),
+        root: [7u8; 32].into(),
+        leaves: 3,
+        branches: None,
+    };
+    let encoded = header.to_vec();
+    assert_eq!(encoded.len(), ExportHeader::LEN);
+    assert_eq!(ExportHeader::from_slice(&encoded), Ok(header));
+
+    let mut invalid = encoded.clone();
+    invalid[0] = b'X';
+    assert!(ExportHeader::from_slice(&invalid).is_err());
+    assert!(ExportHeader::from_slice(&encoded[1..]).is_err());
+}
@@ -0,0 +1,238 @@
+use rocksdb::{
+    prelude::{
+        Delete, DeleteCF, Get, GetColumnFamilys, Iterate, IterateCF, Open, OpenCF, Put, PutCF,
+    },
+    IteratorMode, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    error::Error,
+    traits::{StoreReadOps, Value},
+    BranchKey, SparseMerkleTree, H256,
+};
+
+use crate::{
+    cf_store::ColumnFamilyStoreMultiTree,
+    default_store::DefaultStore,
+    format::{check_format, migrate, read_format, write_format, StoreFormat, FORMAT_KEY},
+    serde::{DecodeError, MergeValueSet, BRANCH_NODE_FORMAT_V1, BRANCH_NODE_FORMAT_VERSION},
+};
+
+use super::{kvs, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type ColumnFamilyStoreMultiTreeSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStoreMultiTree<'a, T, W>>;
+
+// Strip the version header of every branch value, which is how the legacy format stored them.
+fn downgrade_branch(value: &[u8]) -> Vec<u8> {
+    value[1..].to_vec()
+}
+
+#[test]
+fn test_format_record() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+
+    assert_eq!(read_format(&store), Ok(None));
+    assert_eq!(check_format(&store), Ok(()));
+
+    write_format(&store).unwrap();
+    assert_eq!(read_format(&store), Ok(Some(StoreFormat::current())));
+    assert_eq!(check_format(&store), Ok(()));
+}
+
+#[test]
+fn test_format_recorded_with_root() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    // a store created with `new` neither checks nor records the format
+    let mut smt =
+        DefaultStoreSMT::new_with_store(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    smt.update_all(kvs()[..4].to_vec()).unwrap();
+    assert!(db.get(FORMAT_KEY).unwrap().is_none());
+
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(*smt.root(), DefaultStore::open(&db).unwrap());
+    for (key, value) in kvs().into_iter().skip(4) {
+        smt.update(key, value).unwrap();
+    }
+    assert_eq!(
+        db.get(FORMAT_KEY).unwrap().map(|record| record.to_vec()),
+        Some(StoreFormat::current().to_vec())
+    );
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert_eq!(read_format(&store), Ok(Some(StoreFormat::current())));
+
+    // stores written before the format record was introduced are detected by the root branch
+    db.delete(FORMAT_KEY).unwrap();
+    assert_eq!(
+        read_format(&store).map(|f| f.map(|f| f.version)),
+        Ok(Some(BRANCH_NODE_FORMAT_VERSION))
+    );
+}
+
+#[test]
+fn test_refuse_mismatched_merge_value_set() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt =
+        DefaultStoreSMT::new_with_store(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    for (key, value) in kvs() {
+        smt.update(key, value).unwrap();
+    }
+    assert!(DefaultStore::<_, WriteOptions>::open(&db).is_ok());
+
+    let other = match MergeValueSet::current() {
+        MergeValueSet::Basic => MergeValueSet::Trie,
+        MergeValueSet::Trie => MergeValueSet::Basic,
+    };
+    let format = StoreFormat {
+        version: BRANCH_NODE_FORMAT_VERSION,
+        merge_value_set: other,
+    };
+    db.put(FORMAT_KEY, format.to_vec()).unwrap();
+    assert_eq!(
+        read_format(&DefaultStore::<_, WriteOptions>::new(&db)),
+        Ok(Some(format))
+    );
+    match DefaultStore::<_, WriteOptions>::open(&db) {
+        Err(Error::Store(e)) => assert!(e.contains("`trie` feature")),
+        _ => panic!("expected a merge value set mismatch"),
+    }
+
+    // a store created with `new` does not relabel the store with its own format
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(*smt.root(), DefaultStore::new(&db));
+    let (key, _) = kvs()[0].clone();
+    smt.update(key, Word::default()).unwrap();
+    assert_eq!(
+        read_format(&DefaultStore::<_, WriteOptions>::new(&db)),
+        Ok(Some(format))
+    );
+}
+
+#[cfg(not(feature = "trie"))]
+#[test]
+fn test_migrate_trie_store_without_trie_feature() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    // a legacy branch with a `Value` on the left and a `ShortCut` on the right
+    let branch_key = BranchKey::new(u8::MAX, H256::zero());
+    db.put(
+        crate::serde::branch_key_to_vec(&branch_key),
+        [&[4u8][..], &[1u8; 97]].concat(),
+    )
+    .unwrap();
+
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert_eq!(
+        migrate(&store),
+        Err(Error::Store(DecodeError::TrieNotEnabled(4).to_string()))
+    );
+}
+
+#[test]
+fn test_migrate_default_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let root = {
+        let mut smt =
+            DefaultStoreSMT::new_with_store(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+        for (key, value) in kvs() {
+            smt.update(key, value).unwrap();
+        }
+        *smt.root()
+    };
+
+    // rewrite the store into the legacy format
+    let branches = db
+        .iterator(IteratorMode::Start)
+        .filter(|(k, _)| k.len() == 33)
+        .collect::<Vec<_>>();
+    for (key, value) in branches.iter() {
+        db.put(key, downgrade_branch(value)).unwrap();
+    }
+    db.delete(FORMAT_KEY).unwrap();
+
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert_eq!(
+        read_format(&store).map(|f| f.map(|f| f.version)),
+        Ok(Some(BRANCH_NODE_FORMAT_V1))
+    );
+    assert!(matches!(check_format(&store), Err(Error::Store(_))));
+    assert_eq!(
+        StoreReadOps::<Word>::get_branch(&store, &BranchKey::new(u8::MAX, H256::zero())),
+        Err(Error::Store(
+            DecodeError::UnsupportedVersion(BRANCH_NODE_FORMAT_V1).to_string()
+        ))
+    );
+
+    assert_eq!(migrate(&store), Ok(branches.len()));
+    assert_eq!(check_format(&store), Ok(()));
+    // migration is idempotent
+    assert_eq!(migrate(&store), Ok(0));
+
+    let smt = DefaultStoreSMT::new_with_store(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    assert_eq!(*smt.root(), root);
+    let keys = kvs().into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+    let leaves = kvs()
+        .into_iter()
+        .map(|(k, v)| (k, v.to_h256()))
+        .collect::<Vec<_>>();
+    let proof = smt.merkle_proof(keys).unwrap();
+    assert!(proof.verify::<Blake2bHasher>(&root, leaves).unwrap());
+}
+
+#[test]
+fn test_migrate_cf_multi_tree_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    let mut roots = Vec::new();
+    for prefix in [b"tree1.", b"tree2."] {
+        let store =
+            ColumnFamilyStoreMultiTree::<_, WriteOptions>::new(prefix, &db, branch_col, leaf_col);
+        let mut smt = ColumnFamilyStoreMultiTreeSMT::new_with_store(store).unwrap();
+        for (key, value) in kvs().into_iter().skip(roots.len()) {
+            smt.update(key, value).unwrap();
+        }
+        roots.push(*smt.root());
+    }
+
+    // rewrite the first tree into the legacy format
+    let branches = db
+        .iterator_cf(branch_col, IteratorMode::Start)
+        .unwrap()
+        .filter(|(k, _)| k.starts_with(b"tree1.") && k.len() == 6 + 33)
+        .collect::<Vec<_>>();
+    for (key, value) in branches.iter() {
+        db.put_cf(branch_col, key, downgrade_branch(value)).unwrap();
+    }
+    db.delete_cf(branch_col, [b"tree1.", FORMAT_KEY].concat())
+        .unwrap();
+
+    let store1 =
+        ColumnFamilyStoreMultiTree::<_, WriteOptions>::new(b"tree1.", &db, branch_col, leaf_col);
+    let store2 =
+        ColumnFamilyStoreMultiTree::<_, WriteOptions>::new(b"tree2.", &db, branch_col, leaf_col);
+    assert!(check_format(&store1).is_err());
+    assert_eq!(check_format(&store2), Ok(()));
+
+    assert_eq!(migrate(&store1), Ok(branches.len()));
+    assert_eq!(migrate(&store2), Ok(0));
+
+    for (prefix, root) in [b"tree1.", b"tree2."].into_iter().zip(roots) {
+        let store =
+            ColumnFamilyStoreMultiTree::<_, WriteOptions>::new(prefix, &db, branch_col, leaf_col);
+        assert_eq!(check_format(&store), Ok(()));
+        let smt = ColumnFamilyStoreMultiTreeSMT::new_with_store(store).unwrap();
+        assert_eq!(*smt.root(), root);
+    }
+}
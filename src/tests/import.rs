@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use rocksdb::{
+    prelude::{Get, GetCF, GetColumnFamilys, Open, OpenCF, Put},
+    Direction, Options, ReadOptions, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    traits::{StoreReadOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::Backend,
+    cf_store::{ColumnFamilyStore, ColumnFamilyStoreMultiTree},
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    export::{export_store, export_tree, Crc32, ExportHeader},
+    format::FORMAT_KEY,
+    import::{import_store, import_tree},
+    iter::StoreIterOps,
+    namespace::Namespace,
+    root::{commit_root, read_root},
+    verify::verify_store,
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+
+// Export a tree of the leaves, with its branches if `with_branches`.
+fn export(with_branches: bool) -> Vec<u8> {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+    let mut export = Vec::new();
+    export_store::<Blake2bHasher, _, _>(smt.store(), &mut export, with_branches).unwrap();
+    export
+}
+
+// Replace the checksum of a modified export.
+fn checksum(export: &mut Vec<u8>) {
+    let len = export.len() - 4;
+    let mut crc = Crc32::new();
+    crc.update(&export[..len]);
+    export.truncate(len);
+    export.extend_from_slice(&crc.finish().to_be_bytes());
+}
+
+fn check_imported<S>(store: &S, expected: &MemoryStoreSMT)
+where
+    S: Backend + StoreIterOps + StoreReadOps<Word>,
+    S::DB: GetCF<ReadOptions>,
+{
+    assert_eq!(read_root(store), Ok(Some(*expected.root())));
+    let branches: HashMap<BranchKey, BranchNode> = store
+        .branches(None)
+        .unwrap()
+        .map(|branch| branch.unwrap())
+        .collect();
+    assert_eq!(branches, *expected.store().branches_map());
+    assert!(
+        verify_store::<Blake2bHasher, Word, _>(store, expected.root())
+            .unwrap()
+            .is_ok()
+    );
+}
+
+fn check_empty<S>(store: &S)
+where
+    S: Backend + StoreIterOps,
+    S::DB: GetCF<ReadOptions>,
+{
+    assert_eq!(read_root(store), Ok(None));
+    assert_eq!(
+        store
+            .leaves::<Word>(None, Direction::Forward)
+            .unwrap()
+            .count(),
+        0
+    );
+    assert_eq!(store.branches(None).unwrap().count(), 0);
+}
+
+#[test]
+fn test_import_store_types() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+    let namespace = Namespace::new(b"tree").unwrap();
+    let mut expected = MemoryStoreSMT::default();
+    expected.update_all(kvs()).unwrap();
+    let with_branches = export(true);
+    let without_branches = export(false);
+
+    let store = DefaultStoreMultiTree::<_, WriteOptions>::with_namespace(&namespace, &db);
+    let header =
+        import_store::<Blake2bHasher, Word, _, _>(&store, with_branches.as_slice()).unwrap();
+    assert_eq!(
+        (header.root, header.leaves),
+        (*expected.root(), kvs().len() as u64)
+    );
+    check_imported(&store, &expected);
+
+    let store = ColumnFamilyStoreMultiTree::<_, WriteOptions>::with_namespace(
+        &namespace, &db, branch_col, leaf_col,
+    );
+    import_store::<Blake2bHasher, Word, _, _>(&store, without_branches.as_slice()).unwrap();
+    check_imported(&store, &expected);
+
+    let store = ColumnFamilyStore::<_, WriteOptions>::new(&db, branch_col, leaf_col);
+    let mut smt =
+        import_tree::<Blake2bHasher, Word, _, _>(store, with_branches.as_slice()).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    check_imported(smt.store(), &expected);
+    let (key, value) = kvs()[0].clone();
+    let proof = smt.merkle_proof(vec![key]).unwrap();
+    assert!(proof
+        .verify::<Blake2bHasher>(expected.root(), vec![(key, value.to_h256())])
+        .unwrap());
+    smt.update(key, Word::default()).unwrap();
+    expected.update(key, Word::default()).unwrap();
+    assert_eq!(smt.root(), expected.root());
+
+    // a tree exported after an update round trips
+    let mut export = Vec::new();
+    export_tree(&smt, &mut export, true).unwrap();
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    import_store::<Blake2bHasher, Word, _, _>(&store, export.as_slice()).unwrap();
+    check_imported(&store, &expected);
+
+    // only into an empty store
+    assert!(import_store::<Blake2bHasher, Word, _, _>(&store, with_branches.as_slice()).is_err());
+    check_imported(&store, &expected);
+}
+
+#[test]
+fn test_import_rollback() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    // a record of the application, in the key space of the store
+    db.put(b"app", b"record").unwrap();
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    let import = |export: &[u8]| import_store::<Blake2bHasher, Word, _, _>(&store, export);
+    let export = export(true);
+    // the root of the header
+    let root = ExportHeader::LEN - 16 - 32;
+
+    // a root which does not match the leaves
+    let mut wrong_root = export.clone();
+    wrong_root[root] ^= 1;
+    checksum(&mut wrong_root);
+    assert!(import(&wrong_root).is_err());
+    check_empty(&store);
+
+    // an invalid checksum
+    let mut corrupted = export.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 1;
+    assert!(import(&corrupted).is_err());
+    check_empty(&store);
+
+    // a corrupted value
+    let mut corrupted = export.clone();
+    corrupted[ExportHeader::LEN + 36] ^= 1;
+    assert!(import(&corrupted).is_err());
+    check_empty(&store);
+
+    // a truncated export
+    assert!(import(&export[..export.len() - 40]).is_err());
+    check_empty(&store);
+
+    // trailing bytes
+    let mut trailing = export.clone();
+    trailing.push(0);
+    assert!(import(&trailing).is_err());
+    check_empty(&store);
+
+    // another hasher
+    let mut other_hasher = export.clone();
+    other_hasher[9] = 2;
+    checksum(&mut other_hasher);
+    assert!(import(&other_hasher).is_err());
+    check_empty(&store);
+
+    // record lengths beyond the export, or beyond the longest branch node
+    let mut long_leaf = export.clone();
+    long_leaf[ExportHeader::LEN + 32..ExportHeader::LEN + 36]
+        .copy_from_slice(&u32::MAX.to_be_bytes());
+    checksum(&mut long_leaf);
+    assert!(import(&long_leaf).is_err());
+    check_empty(&store);
+    let mut first_branch = ExportHeader::LEN;
+    for _ in kvs() {
+        let len = &export[first_branch + 32..first_branch + 36];
+        first_branch += 36 + u32::from_be_bytes(len.try_into().unwrap()) as usize;
+    }
+    let mut long_branch = export.clone();
+    long_branch[first_branch + 33..first_branch + 37].copy_from_slice(&133u32.to_be_bytes());
+    checksum(&mut long_branch);
+    assert!(import(&long_branch).is_err());
+    check_empty(&store);
+
+    // only the records written by the imports were deleted
+    assert!(db.get(FORMAT_KEY).unwrap().is_none());
+    assert_eq!(db.get(b"app").unwrap().unwrap().as_ref(), b"record");
+    assert!(import(&export).is_ok());
+    assert_eq!(db.get(b"app").unwrap().unwrap().as_ref(), b"record");
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rocksdb::{
+    prelude::{GetColumnFamilys, Open, OpenCF, Put},
+    Direction, OptimisticTransactionDB, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, BranchKey, BranchNode, SparseMerkleTree, H256};
+
+use crate::{
+    cf_store::{ColumnFamilyStore, OwnedColumnFamilyStoreMultiTree},
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    iter::StoreIterOps,
+    namespace::Namespace,
+    root::{commit_root, open_tree},
+    serde::branch_key_to_vec,
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
+type OwnedColumnFamilyStoreMultiSMT<T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, OwnedColumnFamilyStoreMultiTree<T, W>>;
+
+fn sorted_kvs() -> Vec<(H256, String)> {
+    let mut kvs: Vec<_> = kvs()
+        .into_iter()
+        .map(|(key, value)| (key, value.0))
+        .collect();
+    // the byte order of the keys, not the order of `H256::cmp`
+    kvs.sort_by(|(a, _), (b, _)| a.as_slice().cmp(b.as_slice()));
+    kvs
+}
+
+fn leaves<S: StoreIterOps>(
+    store: &S,
+    from: Option<&H256>,
+    direction: Direction,
+) -> Vec<(H256, String)> {
+    store
+        .leaves::<Word>(from, direction)
+        .unwrap()
+        .map(|(key, value)| (key, value.0))
+        .collect()
+}
+
+// Check the leaves of a store holding `kvs`, from every start key and in both directions.
+fn check_leaves<S: StoreIterOps>(store: &S) {
+    let expected = sorted_kvs();
+    assert_eq!(leaves(store, None, Direction::Forward), expected);
+    let reversed: Vec<_> = expected.iter().rev().cloned().collect();
+    assert_eq!(leaves(store, None, Direction::Reverse), reversed);
+
+    for (i, (key, _)) in expected.iter().enumerate() {
+        assert_eq!(
+            leaves(store, Some(key), Direction::Forward),
+            expected[i..].to_vec()
+        );
+        assert_eq!(
+            leaves(store, Some(key), Direction::Reverse),
+            reversed[expected.len() - 1 - i..].to_vec()
+        );
+    }
+
+    // a start key which is not stored
+    let mut from = expected[3].0;
+    from.set_bit(250);
+    assert!(from.as_slice() > expected[3].0.as_slice());
+    assert!(from.as_slice() < expected[4].0.as_slice());
+    assert_eq!(
+        leaves(store, Some(&from), Direction::Forward),
+        expected[4..].to_vec()
+    );
+    assert_eq!(
+        leaves(store, Some(&from), Direction::Reverse),
+        reversed[expected.len() - 4..].to_vec()
+    );
+}
+
+#[test]
+fn test_leaves_of_default_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> = open_tree(DefaultStore::new(&db)).unwrap();
+    assert!(leaves(smt.store(), None, Direction::Forward).is_empty());
+    smt.update_all(kvs()).unwrap();
+    // the branches and the root and format records are not leaves
+    commit_root(&smt).unwrap();
+    check_leaves(smt.store());
+
+    // removed leaves are not stored
+    smt.update(kvs()[0].0, Word::default()).unwrap();
+    let expected: Vec<_> = sorted_kvs()
+        .into_iter()
+        .filter(|(key, _)| *key != kvs()[0].0)
+        .collect();
+    assert_eq!(leaves(smt.store(), None, Direction::Forward), expected);
+}
+
+#[test]
+fn test_leaves_of_multi_tree_store_snapshot() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    // the trees around a tree, and a prefix of `0xff` bytes which has no end key
+    let tree = Namespace::new(b"tree").unwrap();
+    let tree1 = Namespace::new(b"tree1").unwrap();
+    let tree2 = Namespace::new(b"tree2").unwrap();
+    let prefixes: [&[u8]; 4] = [tree.as_bytes(), tree1.as_bytes(), tree2.as_bytes(), b"\xff"];
+    for prefix in prefixes {
+        let mut smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &db)).unwrap();
+        smt.update_all(kvs()).unwrap();
+        commit_root(&smt).unwrap();
+    }
+
+    let snapshot = db.snapshot();
+    let mut smt: DefaultStoreMultiSMT<_, WriteOptions> =
+        open_tree(DefaultStoreMultiTree::with_namespace(&tree1, &db)).unwrap();
+    smt.update_all(vec![(H256::zero(), Word("zero".to_string()))])
+        .unwrap();
+    for prefix in prefixes {
+        let store: DefaultStoreMultiTree<_, ()> = DefaultStoreMultiTree::new(prefix, &snapshot);
+        check_leaves(&store);
+    }
+    let store: DefaultStoreMultiTree<_, ()> = DefaultStoreMultiTree::with_namespace(&tree1, &db);
+    assert_eq!(
+        leaves(&store, None, Direction::Forward)[0],
+        (H256::zero(), "zero".to_string())
+    );
+}
+
+#[test]
+fn test_leaves_of_cf_stores() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = Arc::new(
+        OptimisticTransactionDB::open_cf(
+            &options,
+            tmp_dir.path(),
+            vec!["cf1", "cf2", "cf3", "cf4"],
+        )
+        .unwrap(),
+    );
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    let tx = db.transaction_default();
+    let mut smt: ColumnFamilyStoreSMT<_, ()> =
+        open_tree(ColumnFamilyStore::new(&tx, branch_col, leaf_col)).unwrap();
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+    // a transaction iterates over its own writes
+    check_leaves(smt.store());
+    tx.commit().unwrap();
+    let snapshot = db.snapshot();
+    check_leaves(&ColumnFamilyStore::<_, ()>::new(
+        &snapshot, branch_col, leaf_col,
+    ));
+
+    let tree = Namespace::new(b"tree").unwrap();
+    let mut smt: OwnedColumnFamilyStoreMultiSMT<_, WriteOptions> = open_tree(
+        OwnedColumnFamilyStoreMultiTree::with_namespace(&tree, db.clone(), "cf3", "cf4").unwrap(),
+    )
+    .unwrap();
+    smt.update_all(kvs()).unwrap();
+    check_leaves(smt.store());
+}
+
+fn branches<S: StoreIterOps>(store: &S, height: Option<u8>) -> Vec<(BranchKey, BranchNode)> {
+    store
+        .branches(height)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+// Check the branches of a store holding `kvs` against the branches of a memory store.
+fn check_branches<S: StoreIterOps>(store: &S) {
+    let mut expected = MemoryStoreSMT::default();
+    expected.update_all(kvs()).unwrap();
+    let expected = expected.store().branches_map();
+
+    let all = branches(store, None);
+    assert_eq!(all.len(), expected.len());
+    assert_eq!(all.iter().cloned().collect::<HashMap<_, _>>(), *expected);
+    let keys: Vec<_> = all.iter().map(|(key, _)| branch_key_to_vec(key)).collect();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+    for height in [0, 115, 254, 255] {
+        let at_height = branches(store, Some(height));
+        assert_eq!(
+            at_height,
+            all.iter()
+                .filter(|(key, _)| key.height == height)
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+    }
+    assert_eq!(branches(store, Some(255)).len(), 1);
+}
+
+#[test]
+fn test_branches_of_default_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> = open_tree(DefaultStore::new(&db)).unwrap();
+    assert!(branches(smt.store(), None).is_empty());
+    smt.update_all(kvs()).unwrap();
+    // the leaves and the root and format records, at the height of their first byte, are not branches
+    commit_root(&smt).unwrap();
+    check_branches(smt.store());
+
+    // a corrupted branch is returned as an error
+    let (key, _) = branches(smt.store(), Some(255)).remove(0);
+    db.put(branch_key_to_vec(&key), [0xff]).unwrap();
+    let mut iter = smt.store().branches(Some(255)).unwrap();
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_branches_of_multi_tree_and_cf_stores() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db =
+        OptimisticTransactionDB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    let tree = Namespace::new(b"tree").unwrap();
+    let tree1 = Namespace::new(b"tree1").unwrap();
+    for namespace in [&tree, &tree1] {
+        let mut smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::with_namespace(namespace, &db)).unwrap();
+        smt.update_all(kvs()).unwrap();
+        commit_root(&smt).unwrap();
+    }
+    let mut smt: ColumnFamilyStoreSMT<_, WriteOptions> =
+        open_tree(ColumnFamilyStore::new(&db, branch_col, leaf_col)).unwrap();
+    smt.update_all(kvs()).unwrap();
+
+    let snapshot = db.snapshot();
+    for namespace in [&tree, &tree1] {
+        check_branches(&DefaultStoreMultiTree::<_, ()>::with_namespace(
+            namespace, &snapshot,
+        ));
+    }
+    check_branches(&ColumnFamilyStore::<_, ()>::new(
+        &snapshot, branch_col, leaf_col,
+    ));
+}
@@ -0,0 +1,115 @@
+use rocksdb::{prelude::Open, WriteOptions, DB};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+use crate::{
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    iter::StoreIterOps,
+    journal::{
+        commit_tree, open_tree, read_journal, rollback, rollback_to, truncate_journal, JournalStore,
+    },
+    namespace::Namespace,
+    root::{self, commit_root, read_root},
+};
+
+use super::{expected_tree, kvs, updates, Word};
+
+type JournalStoreSMT<S> = SparseMerkleTree<Blake2bHasher, Word, JournalStore<S, Word>>;
+
+// Check that the tree of `store` is the tree after the first `commits` updates, with no other nodes stored.
+fn check_tree(store: DefaultStoreMultiTree<DB, WriteOptions>, commits: usize) {
+    let expected = expected_tree(&updates()[..commits]);
+    assert_eq!(
+        store
+            .leaves::<Word>(None, rocksdb::Direction::Forward)
+            .unwrap()
+            .count(),
+        expected.store().leaves_map().len()
+    );
+    assert_eq!(
+        store.branches(None).unwrap().count(),
+        expected.store().branches_map().len()
+    );
+    let smt: SparseMerkleTree<Blake2bHasher, Word, _> = root::open_tree(store).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    for (key, _) in kvs() {
+        assert_eq!(
+            smt.get(&key).unwrap().to_h256(),
+            expected.get(&key).unwrap().to_h256()
+        );
+    }
+}
+
+#[test]
+fn test_rollback() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let namespace = Namespace::new(b"tree").unwrap();
+    let store = || DefaultStoreMultiTree::<_, WriteOptions>::with_namespace(&namespace, &db);
+
+    let mut roots = vec![H256::zero()];
+    for leaves in updates() {
+        let mut smt: JournalStoreSMT<_> = open_tree(store()).unwrap();
+        smt.update_all(leaves).unwrap();
+        commit_tree(&mut smt).unwrap();
+        assert!(smt.store().is_empty());
+        roots.push(*smt.root());
+    }
+    let journal = read_journal(&store()).unwrap();
+    assert_eq!(journal.len(), 3);
+    for (i, entry) in journal.iter().enumerate() {
+        assert_eq!(entry.sequence, i as u64);
+        assert_eq!((entry.old_root, entry.new_root), (roots[i], roots[i + 1]));
+    }
+    check_tree(store(), 3);
+
+    assert_eq!(rollback(&store(), 1), Ok(roots[2]));
+    assert_eq!(read_root(&store()), Ok(Some(roots[2])));
+    check_tree(store(), 2);
+    assert!(rollback(&store(), 3).is_err());
+    assert!(rollback_to(&store(), &roots[3]).is_err());
+
+    // the rolled back commit can be committed again
+    let mut smt: JournalStoreSMT<_> = open_tree(store()).unwrap();
+    smt.update_all(updates()[2].clone()).unwrap();
+    commit_tree(&mut smt).unwrap();
+    assert_eq!(*smt.root(), roots[3]);
+    assert_eq!(read_journal(&store()).unwrap().len(), 3);
+
+    assert_eq!(rollback_to(&store(), &roots[3]), Ok(0));
+    assert_eq!(rollback_to(&store(), &roots[1]), Ok(2));
+    check_tree(store(), 1);
+    assert_eq!(rollback_to(&store(), &H256::zero()), Ok(1));
+    check_tree(store(), 0);
+    assert!(read_journal(&store()).unwrap().is_empty());
+}
+
+#[test]
+fn test_rollback_needs_journal() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let store = || DefaultStore::<_, WriteOptions>::new(&db);
+
+    let mut roots = Vec::new();
+    for leaves in updates().into_iter().take(2) {
+        let mut smt: JournalStoreSMT<_> = open_tree(store()).unwrap();
+        smt.update_all(leaves).unwrap();
+        commit_tree(&mut smt).unwrap();
+        roots.push(*smt.root());
+    }
+    // a discarded update is not journaled
+    let mut smt: JournalStoreSMT<_> = open_tree(store()).unwrap();
+    smt.update_all(updates()[2].clone()).unwrap();
+    smt.store_mut().discard();
+    drop(smt);
+    assert_eq!(truncate_journal(&store(), 1), Ok(1));
+    assert_eq!(read_journal(&store()).unwrap().len(), 1);
+    assert!(rollback_to(&store(), &H256::zero()).is_err());
+
+    // a commit without the journal can not be rolled back
+    let mut smt: SparseMerkleTree<Blake2bHasher, Word, _> = root::open_tree(store()).unwrap();
+    smt.update_all(updates()[2].clone()).unwrap();
+    commit_root(&smt).unwrap();
+    assert!(rollback(&store(), 1).is_err());
+    assert_eq!(read_root(&store()), Ok(Some(*smt.root())));
+    assert_eq!(read_journal(&store()).unwrap()[0].new_root, roots[1]);
+}
@@ -4,8 +4,28 @@ use sparse_merkle_tree::{
     blake2b::Blake2bHasher, default_store::DefaultStore, traits::Value, SparseMerkleTree, H256,
 };
 
+mod batch_read;
+mod batch_store;
+mod bulk_load;
+mod cached_store;
 mod cf_store;
 mod default_store;
+mod drop;
+mod export;
+mod format;
+mod import;
+mod iter;
+mod journal;
+mod namespace;
+mod overlay_store;
+mod prune;
+mod range;
+mod registry;
+mod repair;
+mod root;
+mod serde;
+mod verify;
+mod versioned;
 
 #[derive(Default, Clone)]
 pub struct Word(String);
@@ -33,6 +53,12 @@ impl From<DBVector> for Word {
     }
 }
 
+impl From<Box<[u8]>> for Word {
+    fn from(slice: Box<[u8]>) -> Self {
+        Word(String::from_utf8(slice.into_vec()).expect("stored value is utf8"))
+    }
+}
+
 impl AsRef<[u8]> for Word {
     fn as_ref(&self) -> &[u8] {
         self.0.as_bytes()
@@ -43,4 +69,42 @@ pub fn new_blake2b() -> Blake2b {
     Blake2bBuilder::new(32).personal(b"SMT").build()
 }
 
+/// The words of a sentence, keyed by the hash of their index.
+pub fn kvs() -> Vec<(H256, Word)> {
+    "The quick brown fox jumps over the lazy dog"
+        .split_whitespace()
+        .enumerate()
+        .map(|(i, word)| {
+            let mut buf = [0u8; 32];
+            let mut hasher = new_blake2b();
+            hasher.update(&(i as u32).to_le_bytes());
+            hasher.finalize(&mut buf);
+            (buf.into(), Word(word.to_string()))
+        })
+        .collect()
+}
+
+/// The leaves of three successive updates of a tree, which insert, update and delete leaves of `kvs`.
+pub fn updates() -> Vec<Vec<(H256, Word)>> {
+    let kvs = kvs();
+    vec![
+        kvs[..5].to_vec(),
+        vec![
+            (kvs[0].0, Word::default()),
+            (kvs[1].0, Word("updated".to_string())),
+            kvs[5].clone(),
+        ],
+        vec![(kvs[1].0, Word::default()), kvs[6].clone(), kvs[7].clone()],
+    ]
+}
+
+/// The tree in memory after `updates`, applied in order.
+pub fn expected_tree(updates: &[Vec<(H256, Word)>]) -> MemoryStoreSMT {
+    let mut tree = MemoryStoreSMT::default();
+    for leaves in updates {
+        tree.update_all(leaves.clone()).unwrap();
+    }
+    tree
+}
+
 pub type MemoryStoreSMT = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<Word>>;
@@ -0,0 +1,98 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, OpenCF},
+    Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree};
+
+use crate::{
+    cf_store::ColumnFamilyStoreMultiTree,
+    default_store::DefaultStoreMultiTree,
+    namespace::{check_prefixes, Namespace},
+    root::{commit_root, open_tree},
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStoreMultiTree<'a, T, W>>;
+
+#[test]
+fn test_namespace() {
+    let namespace = Namespace::new(b"tree1").unwrap();
+    assert_eq!(namespace.as_bytes(), b"\x05tree1");
+    assert_eq!(namespace.name(), b"tree1");
+    assert_eq!(Namespace::from_prefix(namespace.as_bytes()), Ok(namespace));
+    assert_eq!(Namespace::new(b"").unwrap().as_bytes(), b"\x00");
+    assert!(Namespace::from_prefix(b"\x05tree").is_err());
+    assert!(Namespace::from_prefix(b"").is_err());
+    assert!(Namespace::new(&[b'a'; Namespace::MAX_NAME_LEN]).is_ok());
+    assert!(Namespace::new(&[b'a'; Namespace::MAX_NAME_LEN + 1]).is_err());
+}
+
+#[test]
+fn test_check_prefixes() {
+    assert!(check_prefixes(&[b"tree1.", b"tree2."]).is_ok());
+    assert!(check_prefixes(&[&b"tree"[..], b"tree1"]).is_err());
+    assert!(check_prefixes(&[&b"tree1"[..], b"tree2", b"tree"]).is_err());
+    assert!(check_prefixes(&[&b""[..], b"tree"]).is_err());
+    assert!(check_prefixes(&[b"tree", b"tree"]).is_err());
+
+    let names: [&[u8]; 5] = [b"", b"tree", b"tree1", b"tree\xff", b"\x04tree"];
+    let namespaces: Vec<Namespace> = names
+        .iter()
+        .map(|name| Namespace::new(name).unwrap())
+        .collect();
+    assert!(check_prefixes(&namespaces).is_ok());
+}
+
+#[test]
+fn test_namespaced_trees() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    // the raw prefixes of these names would overlap
+    let names: [&[u8]; 3] = [b"tree", b"tree1", b"tree\xff"];
+    let namespaces: Vec<Namespace> = names
+        .iter()
+        .map(|name| Namespace::new(name).unwrap())
+        .collect();
+    let mut roots = Vec::new();
+    for (i, namespace) in namespaces.iter().enumerate() {
+        let kvs: Vec<_> = kvs().into_iter().skip(i).collect();
+        let mut expected = MemoryStoreSMT::default();
+        expected.update_all(kvs.clone()).unwrap();
+
+        let mut smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::with_namespace(namespace, &db)).unwrap();
+        smt.update_all(kvs.clone()).unwrap();
+        commit_root(&smt).unwrap();
+        assert_eq!(smt.root(), expected.root());
+
+        let mut smt: ColumnFamilyStoreMultiSMT<_, WriteOptions> = open_tree(
+            ColumnFamilyStoreMultiTree::with_namespace(namespace, &db, branch_col, leaf_col),
+        )
+        .unwrap();
+        smt.update_all(kvs).unwrap();
+        commit_root(&smt).unwrap();
+        assert_eq!(smt.root(), expected.root());
+        roots.push(*expected.root());
+    }
+
+    for (namespace, root) in namespaces.iter().zip(roots) {
+        let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+            open_tree(DefaultStoreMultiTree::with_namespace(namespace, &db)).unwrap();
+        assert_eq!(*smt.root(), root);
+        let smt: ColumnFamilyStoreMultiSMT<_, WriteOptions> = open_tree(
+            ColumnFamilyStoreMultiTree::with_namespace(namespace, &db, branch_col, leaf_col),
+        )
+        .unwrap();
+        assert_eq!(*smt.root(), root);
+    }
+}
@@ -0,0 +1,144 @@
+use rocksdb::{
+    prelude::{Iterate, Open},
+    IteratorMode, OptimisticTransactionDB, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    default_store::DefaultStore as MemoryStore,
+    error::Error,
+    traits::{StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    default_store::DefaultStore,
+    overlay_store::{open_tree, OverlayStore},
+    root::{self, commit_root, read_root, write_root},
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type OverlayStoreSMT<S> = SparseMerkleTree<Blake2bHasher, Word, OverlayStore<S, Word>>;
+
+#[test]
+fn test_speculative_root() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    let mut smt: DefaultStoreSMT<_, _> = root::open_tree(DefaultStore::new(&db)).unwrap();
+    smt.update_all(kvs().into_iter().take(5).collect()).unwrap();
+    commit_root(&smt).unwrap();
+    let committed_root = *smt.root();
+    let records: Vec<_> = db.iterator(IteratorMode::Start).collect();
+
+    let mut overlay: OverlayStoreSMT<_> =
+        open_tree(DefaultStore::<_, WriteOptions>::new(&db)).unwrap();
+    assert_eq!(*overlay.root(), committed_root);
+    overlay
+        .update_all(kvs().into_iter().skip(5).collect())
+        .unwrap();
+    overlay.update(kvs()[0].0, Word::default()).unwrap();
+    assert!(!overlay.store().is_empty());
+
+    let mut expected = MemoryStoreSMT::default();
+    expected
+        .update_all(kvs().into_iter().skip(1).collect())
+        .unwrap();
+    assert_eq!(overlay.root(), expected.root());
+
+    overlay.store_mut().discard();
+    assert!(overlay.store().is_empty());
+    let store = overlay.take_store().into_inner();
+    assert_eq!(
+        db.iterator(IteratorMode::Start).collect::<Vec<_>>(),
+        records
+    );
+    assert_eq!(read_root(&store), Ok(Some(committed_root)));
+}
+
+#[test]
+fn test_flush_overlay() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+
+    let tx = db.transaction_default();
+    let mut smt: OverlayStoreSMT<_> = open_tree(DefaultStore::<_, ()>::new(&tx)).unwrap();
+    smt.update_all(kvs()).unwrap();
+    let root = *smt.root();
+    smt.store_mut().flush().unwrap();
+    assert!(smt.store().is_empty());
+    write_root(smt.store().inner(), &root).unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let smt: DefaultStoreSMT<_, ()> = root::open_tree(DefaultStore::new(&snapshot)).unwrap();
+    assert_eq!(*smt.root(), root);
+    let expected = DefaultStoreSMT::<_, ()>::new_with_store(DefaultStore::new(&snapshot)).unwrap();
+    assert_eq!(*expected.root(), root);
+}
+
+// A memory store whose first write of a leaf fails.
+#[derive(Default)]
+struct FlakyStore {
+    inner: MemoryStore<Word>,
+    failed: bool,
+}
+
+impl StoreReadOps<Word> for FlakyStore {
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        self.inner.get_branch(branch_key)
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<Word>, Error> {
+        self.inner.get_leaf(leaf_key)
+    }
+}
+
+impl StoreWriteOps<Word> for FlakyStore {
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        self.inner.insert_branch(node_key, branch)
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: Word) -> Result<(), Error> {
+        if !self.failed {
+            self.failed = true;
+            return Err(Error::Store("leaf write failed".to_string()));
+        }
+        self.inner.insert_leaf(leaf_key, leaf)
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        self.inner.remove_branch(node_key)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.inner.remove_leaf(leaf_key)
+    }
+}
+
+#[test]
+fn test_retry_failed_flush() {
+    let mut smt: OverlayStoreSMT<_> =
+        SparseMerkleTree::new_with_store(OverlayStore::new(FlakyStore::default())).unwrap();
+    smt.update_all(kvs()).unwrap();
+    let root = *smt.root();
+    let len = smt.store().len();
+
+    // a failed flush keeps every pending write
+    assert!(smt.store_mut().flush().is_err());
+    assert_eq!(smt.store().len(), len);
+
+    let mut store = smt.take_store();
+    store.flush().unwrap();
+    assert!(store.is_empty());
+    let smt = MemoryStoreSMT::new_with_store(store.into_inner().inner).unwrap();
+    assert_eq!(*smt.root(), root);
+    let keys = kvs().into_iter().map(|(key, _)| key).collect();
+    let leaves = kvs()
+        .into_iter()
+        .map(|(key, value)| (key, value.to_h256()))
+        .collect();
+    let proof = smt.merkle_proof(keys).unwrap();
+    assert!(proof.verify::<Blake2bHasher>(&root, leaves).unwrap());
+}
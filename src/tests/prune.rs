@@ -0,0 +1,172 @@
+use rocksdb::{
+    prelude::{Iterate, Open},
+    IteratorMode, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+use crate::{
+    backend::KeySpace,
+    namespace::Namespace,
+    prune::Pruner,
+    versioned::{open_tree, VersionedStore},
+};
+
+use super::{expected_tree, kvs, updates, Word};
+
+type VersionedStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, VersionedStore<'a, T, W>>;
+
+// The leaves of the versions, the updates then two more which delete leaves and insert them again.
+fn versions() -> Vec<Vec<(H256, Word)>> {
+    let kvs = kvs();
+    let mut versions = updates();
+    versions.push(vec![(kvs[2].0, Word::default()), kvs[0].clone()]);
+    versions.push(vec![(kvs[3].0, Word::default()), kvs[8].clone()]);
+    versions
+}
+
+fn write_version(db: &DB, space: KeySpace, version: u64) -> H256 {
+    let mut smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::new(db, space, version)).unwrap();
+    smt.update_all(versions()[version as usize - 1].clone())
+        .unwrap();
+    smt.store().commit(smt.root()).unwrap();
+    *smt.root()
+}
+
+fn check_version<T: rocksdb::prelude::IterateCF>(db: &T, space: KeySpace, version: u64) {
+    let expected = expected_tree(&versions()[..version as usize]);
+    let smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::read_only(db, space, version)).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    let keys: Vec<H256> = kvs().into_iter().map(|(key, _)| key).collect();
+    for key in &keys {
+        assert_eq!(
+            smt.get(key).unwrap().to_h256(),
+            expected.get(key).unwrap().to_h256()
+        );
+    }
+    assert_eq!(
+        smt.merkle_proof(keys.clone()).unwrap(),
+        expected.merkle_proof(keys).unwrap()
+    );
+}
+
+fn records(db: &DB, prefix: &[u8]) -> usize {
+    db.iterator(IteratorMode::Start)
+        .filter(|(key, _)| key.starts_with(prefix))
+        .count()
+}
+
+#[test]
+fn test_prune_versions() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let namespace = Namespace::new(b"tree").unwrap();
+    let space = KeySpace::new(None, namespace.as_bytes());
+    let roots: Vec<H256> = (1..=4)
+        .map(|version| write_version(&db, space, version))
+        .collect();
+    let store = VersionedStore::<_, WriteOptions>::read_only(&db, space, 4);
+
+    assert!(Pruner::start(&store, &[H256::zero()]).is_err());
+    // the last version must be retained
+    assert!(Pruner::start(&store, &[roots[1]]).is_err());
+
+    let snapshot = db.snapshot();
+    let before = records(&db, namespace.as_bytes());
+    let mut pruner = Pruner::start(&store, &[roots[1], roots[3]]).unwrap();
+    // a version written while pruning is kept
+    let root5 = write_version(&db, space, 5);
+    let mut deleted = 0;
+    let mut steps = 0;
+    while !pruner.is_done() {
+        deleted += pruner.step(10).unwrap();
+        steps += 1;
+    }
+    assert!(steps > 1);
+    assert!(deleted > 0);
+    // the two root records are not counted
+    let after = records(&db, namespace.as_bytes());
+    assert!(after > before - deleted - 2);
+
+    assert_eq!(
+        store.versions(),
+        Ok(vec![(2, roots[1]), (4, roots[3]), (5, root5)])
+    );
+    for version in [2, 4, 5] {
+        check_version(&db, space, version);
+    }
+    for version in [1, 3] {
+        assert!(
+            open_tree::<Blake2bHasher, Word, _, WriteOptions>(VersionedStore::read_only(
+                &db, space, version
+            ))
+            .is_err()
+        );
+    }
+    // a reader of a snapshot taken before pruning still reads the pruned versions
+    for version in 1..=4 {
+        check_version(&snapshot, space, version);
+    }
+
+    // pruning again with the same roots deletes nothing
+    let mut pruner = Pruner::start(&store, &[roots[1], roots[3], root5]).unwrap();
+    assert_eq!(pruner.run(1), Ok(0));
+}
+
+#[test]
+fn test_prune_to_last_version() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let space = KeySpace::new(None, &[]);
+    let roots: Vec<H256> = (1..=5)
+        .map(|version| write_version(&db, space, version))
+        .collect();
+    let store = VersionedStore::<_, WriteOptions>::read_only(&db, space, 5);
+
+    let mut pruner = Pruner::start(&store, &roots[4..]).unwrap();
+    pruner.run(3).unwrap();
+    check_version(&db, space, 5);
+    // only the nodes of the last version and its root record are left
+    let expected = expected_tree(&versions());
+    assert_eq!(
+        records(&db, &[]),
+        expected.store().branches_map().len() + expected.store().leaves_map().len() + 1
+    );
+    assert_eq!(store.versions(), Ok(vec![(5, roots[4])]));
+}
+
+#[test]
+fn test_prune_uncommitted_version() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let space = KeySpace::new(None, &[]);
+    write_version(&db, space, 1);
+    // version 2 is written but not committed, version 3 is written on version 1
+    let mut smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::new(&db, space, 2)).unwrap();
+    smt.update_all(versions()[2].clone()).unwrap();
+    let mut smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::new(&db, space, 3)).unwrap();
+    smt.update_all(versions()[1].clone()).unwrap();
+    smt.store().commit(smt.root()).unwrap();
+    let root = *smt.root();
+
+    let store = VersionedStore::<_, WriteOptions>::read_only(&db, space, 3);
+    Pruner::start(&store, &[root]).unwrap().run(3).unwrap();
+    // the records and the pending marker of version 2 are deleted
+    let expected = expected_tree(&versions()[..2]);
+    assert_eq!(
+        records(&db, &[]),
+        expected.store().branches_map().len() + expected.store().leaves_map().len() + 1
+    );
+    let smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::read_only(&db, space, 3)).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    for (key, _) in kvs() {
+        assert_eq!(
+            smt.get(&key).unwrap().to_h256(),
+            expected.get(&key).unwrap().to_h256()
+        );
+    }
+}
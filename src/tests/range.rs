@@ -0,0 +1,143 @@
+use rocksdb::{prelude::Open, WriteOptions, DB};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+use crate::{
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    namespace::Namespace,
+    range::{range_proof, RangeProof},
+    root::open_tree,
+};
+
+use super::{kvs, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+
+// The leaves of `kvs` ordered by key, in the order of the tree.
+fn sorted_kvs() -> Vec<(H256, Word)> {
+    let mut kvs = kvs();
+    kvs.sort_by_key(|(key, _)| *key);
+    kvs
+}
+
+fn keys<V>(leaves: &[(H256, V)]) -> Vec<H256> {
+    leaves.iter().map(|(key, _)| *key).collect()
+}
+
+// The key right after `key` in the order of the tree.
+fn next_key(key: &H256) -> H256 {
+    let mut next = *key;
+    for height in 0..=u8::MAX {
+        if next.get_bit(height) {
+            next.clear_bit(height);
+        } else {
+            next.set_bit(height);
+            break;
+        }
+    }
+    next
+}
+
+#[test]
+fn test_range_proof() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let kvs = sorted_kvs();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> = open_tree(DefaultStore::new(&db)).unwrap();
+    smt.update_all(kvs.clone()).unwrap();
+    let root = *smt.root();
+
+    let max = H256::from([u8::MAX; 32]);
+    let ranges = [
+        // the whole tree
+        (H256::zero(), max, 0..9),
+        // bounds on leaves
+        (kvs[2].0, kvs[5].0, 2..6),
+        (kvs[0].0, kvs[0].0, 0..1),
+        (kvs[8].0, max, 8..9),
+        // bounds between leaves
+        (next_key(&kvs[2].0), next_key(&kvs[5].0), 3..6),
+        (next_key(&kvs[2].0), kvs[7].0, 3..8),
+        // no leaves
+        (next_key(&kvs[2].0), next_key(&kvs[2].0), 3..3),
+        (next_key(&kvs[8].0), max, 9..9),
+    ];
+    for (start, end, expected) in ranges {
+        let proof = range_proof(&smt, start, end).unwrap();
+        assert_eq!(keys(&proof.leaves), keys(&kvs[expected.clone()]));
+        for ((_, value), (_, expected)) in proof.leaves.iter().zip(&kvs[expected]) {
+            assert_eq!(value.0, expected.0);
+        }
+        assert_eq!(proof.verify::<Blake2bHasher>(&root), Ok(true));
+        assert_eq!(proof.verify::<Blake2bHasher>(&H256::zero()), Ok(false));
+    }
+    assert!(range_proof(&smt, kvs[1].0, kvs[0].0).is_err());
+
+    // an empty tree
+    let namespace = Namespace::new(b"empty").unwrap();
+    let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+        open_tree(DefaultStoreMultiTree::with_namespace(&namespace, &db)).unwrap();
+    let proof = range_proof(&smt, H256::zero(), max).unwrap();
+    assert!(proof.leaves.is_empty());
+    assert_eq!(proof.verify::<Blake2bHasher>(&H256::zero()), Ok(true));
+}
+
+#[test]
+fn test_range_proof_with_omitted_leaves() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let kvs = sorted_kvs();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> = open_tree(DefaultStore::new(&db)).unwrap();
+    smt.update_all(kvs.clone()).unwrap();
+    let root = *smt.root();
+    let (start, end) = (next_key(&kvs[2].0), kvs[7].0);
+
+    // a valid proof of a part of the leaves of the range
+    for omitted in [3, 5, 7] {
+        let leaves: Vec<(H256, Word)> = kvs[3..8]
+            .iter()
+            .filter(|(key, _)| *key != kvs[omitted].0)
+            .cloned()
+            .collect();
+        let mut proof_keys = keys(&leaves);
+        proof_keys.extend([start, end]);
+        proof_keys.sort();
+        proof_keys.dedup();
+        let proof = RangeProof {
+            start,
+            end,
+            leaves,
+            proof: smt.merkle_proof(proof_keys).unwrap(),
+        };
+        if omitted != 7 {
+            // the proof of the other leaves is valid, but the range is incomplete
+            let mut values: Vec<(H256, H256)> = proof
+                .leaves
+                .iter()
+                .map(|(key, value)| (*key, value.to_h256()))
+                .collect();
+            values.push((start, H256::zero()));
+            assert_eq!(
+                proof.proof.clone().verify::<Blake2bHasher>(&root, values),
+                Ok(true)
+            );
+        }
+        assert_eq!(proof.verify::<Blake2bHasher>(&root), Ok(false));
+    }
+
+    // a proof of the range modified by the prover
+    let proof = range_proof(&smt, start, end).unwrap();
+    let mut modified = proof.clone();
+    modified.leaves.remove(2);
+    assert!(!matches!(modified.verify::<Blake2bHasher>(&root), Ok(true)));
+    let mut modified = proof.clone();
+    modified.leaves[2].1 = Word("modified".to_string());
+    assert_eq!(modified.verify::<Blake2bHasher>(&root), Ok(false));
+    let mut modified = proof.clone();
+    modified.end = kvs[8].0;
+    assert!(!matches!(modified.verify::<Blake2bHasher>(&root), Ok(true)));
+    let mut modified = proof;
+    modified.leaves.push(kvs[8].clone());
+    assert_eq!(modified.verify::<Blake2bHasher>(&root), Ok(false));
+}
@@ -0,0 +1,140 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, Iterate, IterateCF, Open, OpenCF},
+    IteratorMode, OptimisticTransactionDB, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, SparseMerkleTree, H256};
+
+use crate::{
+    cf_store::ColumnFamilyStoreMultiTree, default_store::DefaultStoreMultiTree,
+    format::StoreFormat, registry::Registry, root::open_tree,
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStoreMultiTree<'a, T, W>>;
+
+#[test]
+fn test_registry_functions() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let registry = Registry::<_, WriteOptions>::new(&db);
+
+    assert_eq!(registry.get(b"tree"), Ok(None));
+    assert_eq!(registry.list(), Ok(vec![]));
+    let tree = registry.create(b"tree").unwrap();
+    let tree1 = registry.create(b"tree1").unwrap();
+    assert!(registry.create(b"tree").is_err());
+    assert_eq!((tree.id, tree1.id), (0, 1));
+    assert_eq!(tree.root, H256::zero());
+    assert_eq!(tree.leaf_count, 0);
+    assert_eq!(tree.format, StoreFormat::current());
+    assert!(tree.created_at > 0);
+    assert_ne!(tree.namespace(), tree1.namespace());
+    assert_eq!(registry.get(b"tree"), Ok(Some(tree.clone())));
+    assert_eq!(registry.list(), Ok(vec![tree.clone(), tree1.clone()]));
+
+    let namespace = tree.namespace();
+    let mut smt: DefaultStoreMultiSMT<_, _> =
+        open_tree(DefaultStoreMultiTree::with_namespace(&namespace, &db)).unwrap();
+    let mut expected = MemoryStoreSMT::default();
+    let info = registry
+        .update_tree(b"tree", &mut smt, kvs().into_iter().take(5).collect())
+        .unwrap();
+    expected
+        .update_all(kvs().into_iter().take(5).collect())
+        .unwrap();
+    assert_eq!(info.root, *expected.root());
+    assert_eq!(info.leaf_count, 5);
+
+    // remove two leaves, one of them twice, update one and add the others, only the last value of a key counts
+    let mut leaves = vec![
+        (kvs()[0].0, Word::default()),
+        (kvs()[1].0, Word::default()),
+        (kvs()[1].0, Word("again".to_string())),
+        (kvs()[1].0, Word::default()),
+        (kvs()[2].0, Word("updated".to_string())),
+        (kvs()[5].0, Word::default()),
+    ];
+    leaves.extend(kvs().into_iter().skip(6));
+    let info = registry
+        .update_tree(b"tree", &mut smt, leaves.clone())
+        .unwrap();
+    expected.update_all(leaves).unwrap();
+    assert_eq!(info.root, *expected.root());
+    assert_eq!(info.leaf_count, 3 + 3);
+    assert_eq!(registry.get(b"tree"), Ok(Some(info.clone())));
+
+    let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+        open_tree(DefaultStoreMultiTree::with_namespace(&namespace, &db)).unwrap();
+    assert_eq!(*smt.root(), info.root);
+
+    // a store of another tree is refused
+    let namespace1 = tree1.namespace();
+    let mut smt1: DefaultStoreMultiSMT<_, _> =
+        open_tree(DefaultStoreMultiTree::with_namespace(&namespace1, &db)).unwrap();
+    assert!(registry.update_tree(b"tree", &mut smt1, kvs()).is_err());
+    assert!(registry.delete(b"tree", smt1.store()).is_err());
+    registry.update_tree(b"tree1", &mut smt1, kvs()).unwrap();
+
+    registry.delete(b"tree", smt.store()).unwrap();
+    assert_eq!(registry.get(b"tree"), Ok(None));
+    assert_eq!(registry.list().unwrap().len(), 1);
+    assert!(db
+        .iterator(IteratorMode::Start)
+        .all(|(key, _)| !key.starts_with(namespace.as_bytes())));
+    let smt: DefaultStoreMultiSMT<_, WriteOptions> =
+        open_tree(DefaultStoreMultiTree::with_namespace(&namespace, &db)).unwrap();
+    assert_eq!(*smt.root(), H256::zero());
+
+    // ids are not reused
+    assert_eq!(registry.create(b"tree").unwrap().id, 2);
+}
+
+#[test]
+fn test_registry_of_cf_stores_in_transaction() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db =
+        OptimisticTransactionDB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+
+    let tx = db.transaction_default();
+    let registry = Registry::<_, ()>::new(&tx);
+    let info = registry.create(b"tree").unwrap();
+    let namespace = info.namespace();
+    let mut smt: ColumnFamilyStoreMultiSMT<_, ()> = open_tree(
+        ColumnFamilyStoreMultiTree::with_namespace(&namespace, &tx, branch_col, leaf_col),
+    )
+    .unwrap();
+    let info = registry.update_tree(b"tree", &mut smt, kvs()).unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = db.snapshot();
+    let registry = Registry::<_, ()>::new(&snapshot);
+    assert_eq!(registry.list(), Ok(vec![info.clone()]));
+    assert_eq!(info.leaf_count, kvs().len() as u64);
+    let smt: ColumnFamilyStoreMultiSMT<_, ()> = open_tree(
+        ColumnFamilyStoreMultiTree::with_namespace(&namespace, &snapshot, branch_col, leaf_col),
+    )
+    .unwrap();
+    assert_eq!(*smt.root(), info.root);
+
+    let tx = db.transaction_default();
+    let registry = Registry::<_, ()>::new(&tx);
+    let smt: ColumnFamilyStoreMultiSMT<_, ()> = open_tree(
+        ColumnFamilyStoreMultiTree::with_namespace(&namespace, &tx, branch_col, leaf_col),
+    )
+    .unwrap();
+    registry.delete(b"tree", smt.store()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(Registry::<_, ()>::new(&db).list(), Ok(vec![]));
+    for col in [branch_col, leaf_col] {
+        assert_eq!(db.iterator_cf(col, IteratorMode::Start).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use rocksdb::{
+    prelude::{Delete, Get, GetCF, GetColumnFamilys, Open, OpenCF, Put, PutCF},
+    Direction, Options, ReadOptions, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher, traits::StoreReadOps, BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::Backend,
+    cf_store::ColumnFamilyStore,
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    drop::drop_tree,
+    iter::StoreIterOps,
+    namespace::Namespace,
+    repair::rebuild_branches,
+    root::{commit_root, open_tree, read_root},
+    serde::branch_key_to_vec,
+    verify::verify_store,
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
+
+// Check that the store holds the branches and the root of the tree of `leaves`.
+fn check_rebuilt<S>(store: &S, root: H256, leaves: Vec<(H256, Word)>)
+where
+    S: Backend + StoreReadOps<Word> + StoreIterOps,
+    S::DB: GetCF<ReadOptions>,
+{
+    let mut expected = MemoryStoreSMT::default();
+    expected.update_all(leaves).unwrap();
+    assert_eq!(root, *expected.root());
+    assert_eq!(read_root(store), Ok(Some(root)));
+    let branches: HashMap<BranchKey, BranchNode> = store
+        .branches(None)
+        .unwrap()
+        .map(|branch| branch.unwrap())
+        .collect();
+    assert_eq!(branches, *expected.store().branches_map());
+    assert!(verify_store::<Blake2bHasher, Word, _>(store, &root)
+        .unwrap()
+        .is_ok());
+}
+
+#[test]
+fn test_rebuild_branches() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let kvs = kvs();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs.clone()).unwrap();
+    commit_root(&smt).unwrap();
+
+    // missing, corrupted and stale branches
+    let store = smt.store();
+    let mut branch_keys = store
+        .branches(None)
+        .unwrap()
+        .map(|branch| branch.unwrap().0);
+    let missing = branch_keys.next().unwrap();
+    let corrupted = branch_keys.nth(4).unwrap();
+    drop(branch_keys);
+    db.delete(branch_key_to_vec(&missing)).unwrap();
+    db.put(branch_key_to_vec(&corrupted), [0xff; 3]).unwrap();
+    db.put(
+        branch_key_to_vec(&BranchKey::new(3, H256::from([7u8; 32]))),
+        db.get(branch_key_to_vec(&BranchKey::new(u8::MAX, H256::zero())))
+            .unwrap()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let root = rebuild_branches::<Blake2bHasher, Word, _>(store).unwrap();
+    check_rebuilt(store, root, kvs.clone());
+    let smt: DefaultStoreSMT<_, WriteOptions> = open_tree(DefaultStore::new(&db)).unwrap();
+    let (key, value) = kvs[0].clone();
+    assert_eq!(smt.get(&key).unwrap().0, value.0);
+
+    // the trees of other prefixes are not touched
+    let namespace = Namespace::new(b"tree").unwrap();
+    let mut smt: DefaultStoreMultiSMT<_, WriteOptions> = SparseMerkleTree::new(
+        H256::zero(),
+        DefaultStoreMultiTree::with_namespace(&namespace, &db),
+    );
+    smt.update_all(kvs[..1].to_vec()).unwrap();
+    let tree_root = rebuild_branches::<Blake2bHasher, Word, _>(smt.store()).unwrap();
+    check_rebuilt(smt.store(), tree_root, kvs[..1].to_vec());
+    check_rebuilt(&DefaultStore::<_, WriteOptions>::new(&db), root, kvs);
+}
+
+#[test]
+fn test_rebuild_dropped_branches() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let branch_col = db.cf_handle("cf1").unwrap();
+    let leaf_col = db.cf_handle("cf2").unwrap();
+    let store = || ColumnFamilyStore::<_, WriteOptions>::new(&db, branch_col, leaf_col);
+
+    // an empty tree
+    assert_eq!(
+        rebuild_branches::<Blake2bHasher, Word, _>(&store()),
+        Ok(H256::zero())
+    );
+    assert_eq!(read_root(&store()), Ok(Some(H256::zero())));
+
+    let kvs = kvs();
+    let mut smt: ColumnFamilyStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), store());
+    smt.update_all(kvs.clone()).unwrap();
+    // keep the leaves only
+    let leaves: Vec<(H256, Word)> = smt
+        .store()
+        .leaves(None, Direction::Forward)
+        .unwrap()
+        .collect();
+    drop_tree(&store()).unwrap();
+    for (key, value) in leaves {
+        db.put_cf(leaf_col, key.as_slice(), value.0.as_bytes())
+            .unwrap();
+    }
+    assert_eq!(store().branches(None).unwrap().count(), 0);
+
+    let root = rebuild_branches::<Blake2bHasher, Word, _>(&store()).unwrap();
+    assert_eq!(root, *smt.root());
+    check_rebuilt(&store(), root, kvs);
+}
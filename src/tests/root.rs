@@ -0,0 +1,126 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, Open, OpenCF},
+    OptimisticTransactionDB, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+use crate::{
+    cf_store::ColumnFamilyStore,
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    root::{commit_root, open_tree, read_root},
+};
+
+use super::{kvs, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
+
+#[test]
+fn test_reopen_tree_at_committed_root() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = OptimisticTransactionDB::open_default(tmp_dir.path()).unwrap();
+
+    let tx = db.transaction_default();
+    let mut smt: DefaultStoreSMT<_, ()> = open_tree(DefaultStore::new(&tx)).unwrap();
+    assert_eq!(*smt.root(), H256::zero());
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+    tx.commit().unwrap();
+    let root = *smt.root();
+
+    let snapshot = db.snapshot();
+    let store = DefaultStore::<_, ()>::new(&snapshot);
+    assert_eq!(read_root(&store), Ok(Some(root)));
+    let smt: DefaultStoreSMT<_, ()> = open_tree(store).unwrap();
+    assert_eq!(*smt.root(), root);
+    let (key, value) = kvs()[0].clone();
+    let proof = smt.merkle_proof(vec![key]).unwrap();
+    assert!(proof
+        .verify::<Blake2bHasher>(&root, vec![(key, value.to_h256())])
+        .unwrap());
+}
+
+#[test]
+fn test_uncommitted_root_is_not_persisted() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    let mut smt: DefaultStoreSMT<_, _> = open_tree(DefaultStore::new(&db)).unwrap();
+    smt.update_all(kvs()).unwrap();
+    commit_root(&smt).unwrap();
+    let committed_root = *smt.root();
+    smt.update(kvs()[0].0, Word::default()).unwrap();
+
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert_eq!(read_root(&store), Ok(Some(committed_root)));
+    assert_ne!(committed_root, *smt.root());
+    // the branches of a plain DB are written without the root, the stale root is not opened
+    assert!(open_tree::<Blake2bHasher, Word, _>(store).is_err());
+
+    commit_root(&smt).unwrap();
+    let reopened: DefaultStoreSMT<_, WriteOptions> = open_tree(DefaultStore::new(&db)).unwrap();
+    assert_eq!(reopened.root(), smt.root());
+}
+
+#[test]
+fn test_open_tree_without_root_record() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+
+    let mut smt = DefaultStoreSMT::new_with_store(DefaultStore::new(&db)).unwrap();
+    smt.update_all(kvs()).unwrap();
+    let root = *smt.root();
+
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    assert_eq!(read_root(&store), Ok(None));
+    let smt: DefaultStoreSMT<_, _> = open_tree(store).unwrap();
+    assert_eq!(*smt.root(), root);
+}
+
+#[test]
+fn test_roots_of_multiple_trees() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2", "cf3", "cf4"]).unwrap();
+
+    let mut roots = Vec::new();
+    for (i, prefix) in [b"tree1.", b"tree2."].into_iter().enumerate() {
+        let mut smt: DefaultStoreMultiSMT<_, _> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &db)).unwrap();
+        smt.update_all(kvs().into_iter().skip(i).collect()).unwrap();
+        commit_root(&smt).unwrap();
+        roots.push(*smt.root());
+    }
+    for (i, (branch_col, leaf_col)) in [("cf1", "cf2"), ("cf3", "cf4")].into_iter().enumerate() {
+        let branch_col = db.cf_handle(branch_col).unwrap();
+        let leaf_col = db.cf_handle(leaf_col).unwrap();
+        let mut smt: ColumnFamilyStoreSMT<_, _> =
+            open_tree(ColumnFamilyStore::new(&db, branch_col, leaf_col)).unwrap();
+        smt.update_all(kvs().into_iter().skip(i + 2).collect())
+            .unwrap();
+        commit_root(&smt).unwrap();
+        roots.push(*smt.root());
+    }
+
+    let snapshot = db.snapshot();
+    for (prefix, root) in [b"tree1.", b"tree2."].into_iter().zip(&roots) {
+        let smt: DefaultStoreMultiSMT<_, ()> =
+            open_tree(DefaultStoreMultiTree::new(prefix, &snapshot)).unwrap();
+        assert_eq!(smt.root(), root);
+    }
+    for ((branch_col, leaf_col), root) in [("cf1", "cf2"), ("cf3", "cf4")]
+        .into_iter()
+        .zip(&roots[2..])
+    {
+        let branch_col = db.cf_handle(branch_col).unwrap();
+        let leaf_col = db.cf_handle(leaf_col).unwrap();
+        let smt: ColumnFamilyStoreSMT<_, ()> =
+            open_tree(ColumnFamilyStore::new(&snapshot, branch_col, leaf_col)).unwrap();
+        assert_eq!(smt.root(), root);
+    }
+}
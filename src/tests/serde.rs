@@ -0,0 +1,111 @@
+use rocksdb::{
+    prelude::{Open, Put},
+    DB,
+};
+use sparse_merkle_tree::{
+    error::Error, merge::MergeValue, traits::StoreReadOps, BranchKey, BranchNode, H256,
+};
+
+use crate::{
+    default_store::DefaultStore,
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node, DecodeError},
+};
+
+use super::Word;
+
+fn branch_nodes() -> Vec<BranchNode> {
+    let value = MergeValue::Value([1u8; 32].into());
+    let merge_with_zero = MergeValue::MergeWithZero {
+        base_node: [2u8; 32].into(),
+        zero_bits: [3u8; 32].into(),
+        zero_count: 4,
+    };
+    vec![
+        BranchNode {
+            left: value.clone(),
+            right: value.clone(),
+        },
+        BranchNode {
+            left: value.clone(),
+            right: merge_with_zero.clone(),
+        },
+        BranchNode {
+            left: merge_with_zero.clone(),
+            right: value,
+        },
+        BranchNode {
+            left: merge_with_zero.clone(),
+            right: merge_with_zero,
+        },
+    ]
+}
+
+#[test]
+fn test_branch_node_roundtrip() {
+    for node in branch_nodes() {
+        let bytes = branch_node_to_vec(&node);
+        assert_eq!(slice_to_branch_node(&bytes), Ok(node));
+    }
+}
+
+#[test]
+fn test_decode_corrupted_branch_node() {
+    assert_eq!(
+        slice_to_branch_node(&[]),
+        Err(DecodeError::InvalidLength {
+            expected: 1,
+            actual: 0
+        })
+    );
+    let mut unknown_tag = branch_node_to_vec(&branch_nodes()[0]);
+    unknown_tag[1] = 0x7f;
+    assert_eq!(
+        slice_to_branch_node(&unknown_tag),
+        Err(DecodeError::UnknownTag(0x7f))
+    );
+
+    for node in branch_nodes() {
+        let bytes = branch_node_to_vec(&node);
+        let len = bytes.len();
+        assert_eq!(
+            slice_to_branch_node(&bytes[..len - 1]),
+            Err(DecodeError::InvalidLength {
+                expected: len,
+                actual: len - 1
+            })
+        );
+        assert_eq!(
+            slice_to_branch_node(&[bytes.as_slice(), &[0]].concat()),
+            Err(DecodeError::TrailingBytes {
+                expected: len,
+                actual: len + 1
+            })
+        );
+    }
+}
+
+#[cfg(not(feature = "trie"))]
+#[test]
+fn test_decode_trie_branch_node_without_trie_feature() {
+    use crate::serde::{branch_node_merge_value_set, MergeValueSet};
+
+    let mut bytes = branch_node_to_vec(&branch_nodes()[1]);
+    bytes[1] = 4;
+    assert_eq!(
+        slice_to_branch_node(&bytes),
+        Err(DecodeError::TrieNotEnabled(4))
+    );
+    assert_eq!(branch_node_merge_value_set(&bytes), Ok(MergeValueSet::Trie));
+}
+
+#[test]
+fn test_store_surfaces_corrupted_branch_node() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let branch_key = BranchKey::new(0, H256::zero());
+    db.put(branch_key_to_vec(&branch_key), [0u8; 10]).unwrap();
+
+    let store = DefaultStore::<_, ()>::new(&db);
+    let result = StoreReadOps::<Word>::get_branch(&store, &branch_key);
+    assert!(matches!(result, Err(Error::Store(_))));
+}
@@ -0,0 +1,186 @@
+use rocksdb::{
+    prelude::{Get, GetColumnFamilys, Open, OpenCF, Put},
+    Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{
+    blake2b::Blake2bHasher,
+    traits::{StoreWriteOps, Value},
+    BranchKey, SparseMerkleTree, H256,
+};
+
+use crate::{
+    cf_store::ColumnFamilyStore,
+    default_store::{DefaultStore, DefaultStoreMultiTree},
+    iter::StoreIterOps,
+    namespace::Namespace,
+    serde::branch_key_to_vec,
+    verify::{verify_store, verify_tree, Issue, NodeKey},
+};
+
+use super::{kvs, MemoryStoreSMT, Word};
+
+type DefaultStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, DefaultStore<'a, T, W>>;
+type DefaultStoreMultiSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, DefaultStoreMultiTree<'a, T, W>>;
+type ColumnFamilyStoreSMT<'a, T, W> =
+    SparseMerkleTree<Blake2bHasher, Word, ColumnFamilyStore<'a, T, W>>;
+
+#[test]
+fn test_verify_consistent_stores() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = DB::open_cf(&options, tmp_dir.path(), vec!["cf1", "cf2"]).unwrap();
+    let mut expected = MemoryStoreSMT::default();
+    expected.update_all(kvs()).unwrap();
+    let branches = expected.store().branches_map().len();
+
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs()).unwrap();
+    let report = verify_tree(&smt).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.root, *expected.root());
+    assert_eq!((report.branches, report.leaves), (branches, kvs().len()));
+
+    let namespace = Namespace::new(b"tree").unwrap();
+    let mut smt: DefaultStoreMultiSMT<_, WriteOptions> = SparseMerkleTree::new(
+        H256::zero(),
+        DefaultStoreMultiTree::with_namespace(&namespace, &db),
+    );
+    smt.update_all(kvs().into_iter().take(3).collect()).unwrap();
+    let report = verify_tree(&smt).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.leaves, 3);
+
+    let mut smt: ColumnFamilyStoreSMT<_, WriteOptions> = SparseMerkleTree::new(
+        H256::zero(),
+        ColumnFamilyStore::new(
+            &db,
+            db.cf_handle("cf1").unwrap(),
+            db.cf_handle("cf2").unwrap(),
+        ),
+    );
+    let report = verify_tree(&smt).unwrap();
+    assert!(report.is_ok());
+    assert_eq!((report.branches, report.leaves), (0, 0));
+    smt.update_all(kvs()).unwrap();
+    let report = verify_tree(&smt).unwrap();
+    assert!(report.is_ok());
+    assert_eq!((report.branches, report.leaves), (branches, kvs().len()));
+}
+
+#[test]
+fn test_verify_corrupted_store() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs()).unwrap();
+    let root = *smt.root();
+    let (key, value) = kvs()[0].clone();
+    let verify = || {
+        verify_store::<Blake2bHasher, Word, _>(&DefaultStore::<_, WriteOptions>::new(&db), &root)
+            .unwrap()
+            .issues
+    };
+    let mut store = DefaultStore::<_, WriteOptions>::new(&db);
+
+    // the wrong root
+    let wrong_root = H256::from([1u8; 32]);
+    let report = verify_store::<Blake2bHasher, Word, _>(&store, &wrong_root).unwrap();
+    assert_eq!(
+        report.issues,
+        vec![Issue::Mismatch {
+            node: NodeKey::Branch(BranchKey::new(u8::MAX, H256::zero())),
+            expected: wrong_root,
+            actual: root,
+        }]
+    );
+    // a stored tree verified as an empty tree
+    let report = verify_store::<Blake2bHasher, Word, _>(&store, &H256::zero()).unwrap();
+    assert_eq!(
+        report.issues.len(),
+        store.branches(None).unwrap().count() + kvs().len()
+    );
+    assert!(report
+        .issues
+        .iter()
+        .all(|issue| matches!(issue, Issue::Orphan(_))));
+
+    // a modified leaf
+    let modified = Word("modified".to_string());
+    store.insert_leaf(key, modified.clone()).unwrap();
+    assert_eq!(
+        verify(),
+        vec![Issue::Mismatch {
+            node: NodeKey::Leaf(key),
+            expected: value.to_h256(),
+            actual: modified.to_h256(),
+        }]
+    );
+    // a missing leaf
+    StoreWriteOps::<Word>::remove_leaf(&mut store, &key).unwrap();
+    assert_eq!(verify(), vec![Issue::Missing(NodeKey::Leaf(key))]);
+    store.insert_leaf(key, value).unwrap();
+    assert!(verify().is_empty());
+
+    // an orphaned leaf
+    let orphan = H256::from([2u8; 32]);
+    store
+        .insert_leaf(orphan, Word("orphan".to_string()))
+        .unwrap();
+    assert_eq!(verify(), vec![Issue::Orphan(NodeKey::Leaf(orphan))]);
+    StoreWriteOps::<Word>::remove_leaf(&mut store, &orphan).unwrap();
+
+    // a missing branch, the nodes below it are orphaned
+    let (branch_key, _) = store.branches(None).unwrap().next().unwrap().unwrap();
+    StoreWriteOps::<Word>::remove_branch(&mut store, &branch_key).unwrap();
+    let issues = verify();
+    assert_eq!(issues[0], Issue::Missing(NodeKey::Branch(branch_key)));
+    assert!(issues.len() > 1);
+    assert!(issues[1..]
+        .iter()
+        .all(|issue| matches!(issue, Issue::Orphan(_))));
+}
+
+#[test]
+fn test_verify_corrupt_branch() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let mut smt: DefaultStoreSMT<_, WriteOptions> =
+        SparseMerkleTree::new(H256::zero(), DefaultStore::new(&db));
+    smt.update_all(kvs()).unwrap();
+    let store = DefaultStore::<_, WriteOptions>::new(&db);
+    let branches = store.branches(None).unwrap().count();
+
+    // a truncated branch is reported, the walk goes on and the leaves below the lowest branch are orphaned
+    let (branch_key, _) = store.branches(None).unwrap().next().unwrap().unwrap();
+    let key = branch_key_to_vec(&branch_key);
+    let value = db.get(&key).unwrap().unwrap();
+    db.put(&key, &value[..value.len() / 2]).unwrap();
+    let report = verify_tree(&smt).unwrap();
+    assert!(matches!(
+        &report.issues[0],
+        Issue::Corrupt(NodeKey::Branch(corrupt), _) if *corrupt == branch_key
+    ));
+    assert!(report.issues.len() > 1);
+    assert!(report.issues[1..]
+        .iter()
+        .all(|issue| matches!(issue, Issue::Orphan(NodeKey::Leaf(_)))));
+    assert_eq!(report.branches, branches - 1);
+    assert_eq!(report.leaves + report.issues.len() - 1, kvs().len());
+
+    // an unreachable truncated branch is a corrupt orphan
+    let report = verify_store::<Blake2bHasher, Word, _>(&store, &H256::zero()).unwrap();
+    let position = report
+        .issues
+        .iter()
+        .position(|issue| matches!(issue, Issue::Corrupt(NodeKey::Branch(corrupt), _) if *corrupt == branch_key))
+        .unwrap();
+    assert_eq!(
+        report.issues[position + 1],
+        Issue::Orphan(NodeKey::Branch(branch_key))
+    );
+}
@@ -0,0 +1,193 @@
+use rocksdb::{
+    prelude::{GetColumnFamilys, Open, OpenCF},
+    OptimisticTransactionDB, Options, WriteOptions, DB,
+};
+use sparse_merkle_tree::{blake2b::Blake2bHasher, traits::Value, SparseMerkleTree, H256};
+
+use crate::{
+    backend::KeySpace,
+    namespace::Namespace,
+    versioned::{open_tree, VersionedStore},
+};
+
+use super::{expected_tree, kvs, updates, Word};
+
+type VersionedStoreSMT<'a, T, W> = SparseMerkleTree<Blake2bHasher, Word, VersionedStore<'a, T, W>>;
+
+#[test]
+fn test_reopen_versions() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let namespace = Namespace::new(b"tree").unwrap();
+    let space = || KeySpace::new(None, namespace.as_bytes());
+
+    let mut roots = Vec::new();
+    for (version, leaves) in (1..).zip(updates()) {
+        let mut smt: VersionedStoreSMT<_, WriteOptions> =
+            open_tree(VersionedStore::new(&db, space(), version)).unwrap();
+        assert_eq!(
+            smt.root(),
+            expected_tree(&updates()[..version as usize - 1]).root()
+        );
+        smt.update_all(leaves).unwrap();
+        smt.store().commit(smt.root()).unwrap();
+        assert_eq!(
+            smt.root(),
+            expected_tree(&updates()[..version as usize]).root()
+        );
+        roots.push((version, *smt.root()));
+    }
+
+    let store = VersionedStore::<_, WriteOptions>::read_only(&db, space(), 1);
+    assert_eq!(store.versions(), Ok(roots.clone()));
+    assert_eq!(store.committed_root(2), Ok(Some(roots[1].1)));
+    assert_eq!(store.committed_root(4), Ok(None));
+
+    // every version can be reopened read-only, with its leaves and proofs
+    for (version, root) in roots {
+        let expected = expected_tree(&updates()[..version as usize]);
+        let mut smt: VersionedStoreSMT<_, WriteOptions> =
+            open_tree(VersionedStore::read_only(&db, space(), version)).unwrap();
+        assert_eq!(*smt.root(), root);
+        for (key, _) in kvs() {
+            assert_eq!(
+                smt.get(&key).unwrap().to_h256(),
+                expected.get(&key).unwrap().to_h256()
+            );
+        }
+        let keys: Vec<H256> = kvs().into_iter().map(|(key, _)| key).collect();
+        let proof = smt.merkle_proof(keys.clone()).unwrap();
+        assert_eq!(proof, expected.merkle_proof(keys).unwrap());
+        assert!(smt.update(kvs()[8].0, kvs()[8].1.clone()).is_err());
+    }
+
+    // a committed or older version can not be written, a version which was not committed can not be read
+    assert!(
+        open_tree::<Blake2bHasher, Word, _, WriteOptions>(VersionedStore::new(&db, space(), 3))
+            .is_err()
+    );
+    assert!(
+        open_tree::<Blake2bHasher, Word, _, WriteOptions>(VersionedStore::new(&db, space(), 2))
+            .is_err()
+    );
+    assert!(
+        open_tree::<Blake2bHasher, Word, _, WriteOptions>(VersionedStore::read_only(
+            &db,
+            space(),
+            4
+        ))
+        .is_err()
+    );
+    // the versions of another tree are separate
+    let namespace1 = Namespace::new(b"tree1").unwrap();
+    let smt: VersionedStoreSMT<_, WriteOptions> = open_tree(VersionedStore::new(
+        &db,
+        KeySpace::new(None, namespace1.as_bytes()),
+        1,
+    ))
+    .unwrap();
+    assert_eq!(*smt.root(), H256::zero());
+}
+
+#[test]
+fn test_versions_in_transactions() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let db = OptimisticTransactionDB::open_cf(&options, tmp_dir.path(), vec!["cf1"]).unwrap();
+    let col = db.cf_handle("cf1").unwrap();
+    let updates = updates();
+
+    let tx = db.transaction_default();
+    let mut smt: VersionedStoreSMT<_, ()> =
+        open_tree(VersionedStore::new(&tx, KeySpace::new(Some(col), &[]), 1)).unwrap();
+    smt.update_all(updates[0].clone()).unwrap();
+    smt.store().commit(smt.root()).unwrap();
+    tx.commit().unwrap();
+    let root1 = *smt.root();
+
+    // a version which is rolled back can be written again
+    let tx = db.transaction_default();
+    let mut smt: VersionedStoreSMT<_, ()> =
+        open_tree(VersionedStore::new(&tx, KeySpace::new(Some(col), &[]), 2)).unwrap();
+    smt.update_all(updates[2].clone()).unwrap();
+    tx.rollback().unwrap();
+    let tx = db.transaction_default();
+    let mut smt: VersionedStoreSMT<_, ()> =
+        open_tree(VersionedStore::new(&tx, KeySpace::new(Some(col), &[]), 2)).unwrap();
+    assert_eq!(*smt.root(), root1);
+    smt.update_all(updates[1].clone()).unwrap();
+    smt.store().commit(smt.root()).unwrap();
+    tx.commit().unwrap();
+
+    let expected = expected_tree(&updates[..2]);
+    let snapshot = db.snapshot();
+    let smt: VersionedStoreSMT<_, ()> = open_tree(VersionedStore::read_only(
+        &snapshot,
+        KeySpace::new(Some(col), &[]),
+        2,
+    ))
+    .unwrap();
+    assert_eq!(smt.root(), expected.root());
+    let smt: VersionedStoreSMT<_, ()> = open_tree(VersionedStore::read_only(
+        &snapshot,
+        KeySpace::new(Some(col), &[]),
+        1,
+    ))
+    .unwrap();
+    assert_eq!(*smt.root(), root1);
+    assert_eq!(smt.get(&kvs()[0].0).unwrap().0, kvs()[0].1 .0);
+}
+
+#[test]
+fn test_uncommitted_version_is_not_visible() {
+    let tmp_dir = tempfile::Builder::new().tempdir().unwrap();
+    let db = DB::open_default(tmp_dir.path()).unwrap();
+    let space = KeySpace::new(None, &[]);
+    let updates = updates();
+
+    let mut smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::new(&db, space, 1)).unwrap();
+    smt.update_all(updates[0].clone()).unwrap();
+    smt.store().commit(smt.root()).unwrap();
+    // version 2 is written but not committed
+    let mut smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::new(&db, space, 2)).unwrap();
+    smt.update_all(updates[2].clone()).unwrap();
+    assert!(
+        open_tree::<Blake2bHasher, Word, _, WriteOptions>(VersionedStore::new(&db, space, 2))
+            .is_err()
+    );
+
+    // version 3 is written on version 1, the leaves of version 2 are not visible
+    let expected = expected_tree(&updates[..2]);
+    let version1 = expected_tree(&updates[..1]);
+    let mut smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::new(&db, space, 3)).unwrap();
+    assert_eq!(smt.root(), version1.root());
+    for (key, _) in &updates[2] {
+        assert_eq!(
+            smt.get(key).unwrap().to_h256(),
+            version1.get(key).unwrap().to_h256()
+        );
+    }
+    smt.update_all(updates[1].clone()).unwrap();
+    smt.store().commit(smt.root()).unwrap();
+    assert_eq!(smt.root(), expected.root());
+
+    let smt: VersionedStoreSMT<_, WriteOptions> =
+        open_tree(VersionedStore::read_only(&db, space, 3)).unwrap();
+    assert_eq!(smt.root(), expected.root());
+    for (key, _) in kvs() {
+        assert_eq!(
+            smt.get(&key).unwrap().to_h256(),
+            expected.get(&key).unwrap().to_h256()
+        );
+    }
+    let keys: Vec<H256> = kvs().into_iter().map(|(key, _)| key).collect();
+    assert_eq!(
+        smt.merkle_proof(keys.clone()).unwrap(),
+        expected.merkle_proof(keys).unwrap()
+    );
+}
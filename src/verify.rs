@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+
+use rocksdb::Direction;
+#[cfg(feature = "trie")]
+use sparse_merkle_tree::merge::MergeValue;
+use sparse_merkle_tree::{
+    error::Error,
+    merge::merge,
+    traits::{Hasher, StoreReadOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::iter::StoreIterOps;
+
+/// A branch or a leaf of a store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeKey {
+    Branch(BranchKey),
+    Leaf(H256),
+}
+
+/// An inconsistency of the nodes of a store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// A node referenced by its parent branch, or the root branch of a non-zero root, is not stored.
+    Missing(NodeKey),
+    /// The hash of a stored node does not match the hash recorded by its parent branch, or the root.
+    Mismatch {
+        node: NodeKey,
+        expected: H256,
+        actual: H256,
+    },
+    /// A leaf referenced by a shortcut of a branch which is not below that branch, or at another height.
+    Misplaced(NodeKey),
+    /// A stored node which is not reachable from the root.
+    Orphan(NodeKey),
+    /// A stored node which can not be read or decoded, with the error. The nodes below it are not walked.
+    Corrupt(NodeKey, String),
+}
+
+/// The result of `verify_store`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The root the store was verified against.
+    pub root: H256,
+    /// The number of branches and leaves reachable from the root.
+    pub branches: usize,
+    pub leaves: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl VerifyReport {
+    /// Returns true if no issue was found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+// The state of a walk of the tree from the root.
+struct Checker<'a, S> {
+    store: &'a S,
+    report: VerifyReport,
+    // The reachable branches and leaves.
+    branches: HashSet<BranchKey>,
+    leaves: HashSet<H256>,
+}
+
+impl<'a, S> Checker<'a, S> {
+    // Check the stored branch of a child hash, returns the branch to walk if it is stored.
+    fn check_branch<H, V>(
+        &mut self,
+        branch_key: BranchKey,
+        expected: H256,
+    ) -> Option<(BranchKey, BranchNode)>
+    where
+        H: Hasher + Default,
+        S: StoreReadOps<V>,
+    {
+        let branch = match self.store.get_branch(&branch_key) {
+            Ok(Some(branch)) => branch,
+            Ok(None) => {
+                self.report
+                    .issues
+                    .push(Issue::Missing(NodeKey::Branch(branch_key)));
+                return None;
+            }
+            Err(e) => {
+                // reachable, so it is not reported again as an orphan
+                self.branches.insert(branch_key.clone());
+                self.report
+                    .issues
+                    .push(Issue::Corrupt(NodeKey::Branch(branch_key), e.to_string()));
+                return None;
+            }
+        };
+        let actual = merge::<H>(
+            branch_key.height,
+            &branch_key.node_key,
+            &branch.left,
+            &branch.right,
+        )
+        .hash::<H>();
+        if actual != expected {
+            self.report.issues.push(Issue::Mismatch {
+                node: NodeKey::Branch(branch_key.clone()),
+                expected,
+                actual,
+            });
+        }
+        Some((branch_key, branch))
+    }
+
+    // Check the stored leaf of a child hash.
+    fn check_leaf<V>(&mut self, leaf_key: H256, expected: H256) -> Result<(), Error>
+    where
+        V: Value,
+        S: StoreReadOps<V>,
+    {
+        let actual = match self.store.get_leaf(&leaf_key)? {
+            Some(leaf) => leaf.to_h256(),
+            None => {
+                self.report
+                    .issues
+                    .push(Issue::Missing(NodeKey::Leaf(leaf_key)));
+                return Ok(());
+            }
+        };
+        if self.leaves.insert(leaf_key) {
+            self.report.leaves += 1;
+        }
+        if actual != expected {
+            self.report.issues.push(Issue::Mismatch {
+                node: NodeKey::Leaf(leaf_key),
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Verify that the branches and leaves of a store are a consistent tree of `root`.
+///
+/// Walks all branches from the root branch, checks every child hash against the stored child branch or leaf
+/// with the hasher `H`, then scans the store for the nodes which are not reachable from the root.
+/// The keys of the reachable nodes are kept in memory. A branch which can not be read or decoded is reported as
+/// `Issue::Corrupt`, returns an error if the store can not be iterated or a leaf can not be read.
+pub fn verify_store<H, V, S>(store: &S, root: &H256) -> Result<VerifyReport, Error>
+where
+    H: Hasher + Default,
+    V: Value + From<Box<[u8]>>,
+    S: StoreReadOps<V> + StoreIterOps,
+{
+    let mut checker = Checker {
+        store,
+        report: VerifyReport {
+            root: *root,
+            branches: 0,
+            leaves: 0,
+            issues: Vec::new(),
+        },
+        branches: HashSet::new(),
+        leaves: HashSet::new(),
+    };
+    let mut stack = Vec::new();
+    let root_branch_key = BranchKey::new(u8::MAX, H256::zero());
+    // an empty tree has no root branch, a stored one is an orphan
+    if !root.is_zero() {
+        if let Some(branch) = checker.check_branch::<H, V>(root_branch_key, *root) {
+            stack.push(branch);
+        }
+    }
+
+    while let Some((branch_key, branch)) = stack.pop() {
+        checker.report.branches += 1;
+        for (is_right, child) in [(false, &branch.left), (true, &branch.right)] {
+            if child.is_zero() {
+                continue;
+            }
+            let mut child_key = branch_key.node_key;
+            if is_right {
+                child_key.set_bit(branch_key.height);
+            }
+            #[cfg(feature = "trie")]
+            if let MergeValue::ShortCut { key, value, height } = child {
+                // the only leaf below the branch on this side, without the branches between them
+                if *height != branch_key.height
+                    || key.parent_path(branch_key.height) != branch_key.node_key
+                    || key.is_right(branch_key.height) != is_right
+                {
+                    checker
+                        .report
+                        .issues
+                        .push(Issue::Misplaced(NodeKey::Leaf(*key)));
+                }
+                checker.check_leaf::<V>(*key, *value)?;
+                continue;
+            }
+            if branch_key.height == 0 {
+                checker.check_leaf::<V>(child_key, child.hash::<H>())?;
+            } else {
+                let child_branch_key = BranchKey::new(branch_key.height - 1, child_key);
+                if let Some(child_branch) =
+                    checker.check_branch::<H, V>(child_branch_key, child.hash::<H>())
+                {
+                    stack.push(child_branch);
+                }
+            }
+        }
+        checker.branches.insert(branch_key);
+    }
+
+    for (branch_key, branch) in store.branches(None)?.with_keys() {
+        if checker.branches.contains(&branch_key) {
+            continue;
+        }
+        if let Err(e) = branch {
+            checker.report.issues.push(Issue::Corrupt(
+                NodeKey::Branch(branch_key.clone()),
+                e.to_string(),
+            ));
+        }
+        checker
+            .report
+            .issues
+            .push(Issue::Orphan(NodeKey::Branch(branch_key)));
+    }
+    for (leaf_key, _) in store.leaves::<V>(None, Direction::Forward)? {
+        if !checker.leaves.contains(&leaf_key) {
+            checker
+                .report
+                .issues
+                .push(Issue::Orphan(NodeKey::Leaf(leaf_key)));
+        }
+    }
+    Ok(checker.report)
+}
+
+/// Verify the store of a tree against the root of the tree, see `verify_store`.
+pub fn verify_tree<H, V, S>(tree: &SparseMerkleTree<H, V, S>) -> Result<VerifyReport, Error>
+where
+    H: Hasher + Default,
+    V: Value + From<Box<[u8]>>,
+    S: StoreReadOps<V> + StoreIterOps,
+{
+    verify_store::<H, V, S>(tree.store(), tree.root())
+}
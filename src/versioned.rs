@@ -0,0 +1,379 @@
+use rocksdb::{
+    prelude::{DeleteCF, GetCF, IterateCF, PutCF},
+    Direction, ReadOptions,
+};
+use sparse_merkle_tree::{
+    error::Error,
+    traits::{Hasher, StoreReadOps, StoreWriteOps, Value},
+    BranchKey, BranchNode, SparseMerkleTree, H256,
+};
+
+use crate::{
+    backend::KeySpace,
+    serde::{branch_key_to_vec, branch_node_to_vec, slice_to_branch_node},
+};
+
+pub(crate) const BRANCH_TAG: u8 = b'B';
+pub(crate) const LEAF_TAG: u8 = b'L';
+pub(crate) const ROOT_TAG: u8 = b'R';
+// The tag of the marker of a version which is being written, deleted when the version is committed.
+pub(crate) const PENDING_TAG: u8 = b'P';
+
+// The first byte of a record value, a removed node is recorded so that it is not visible in later versions.
+pub(crate) const REMOVED: u8 = 0;
+pub(crate) const PRESENT: u8 = 1;
+
+/// A SMT `Store` implementation which keeps the nodes of every version of a tree, so the tree can be reopened
+/// at any committed version, see `open_tree`.
+///
+/// A node is stored under its key followed by the version which wrote it, and a store of a version reads the node
+/// written by the latest version up to its own. A version is marked as pending by its first write, and the
+/// records of a version which is still pending when the tree is opened by `open_tree` are not visible, so a version
+/// which fails is not read by the later versions, and can not be written again. A store of an older version is
+/// read-only.
+pub struct VersionedStore<'a, T, W> {
+    // The RocksDB database which stores the data, can be a `DB` / `OptimisticTransaction` / `Snapshot` etc.
+    inner: &'a T,
+    // The column family and the prefix of the records of the tree.
+    space: KeySpace<'a>,
+    version: u64,
+    read_only: bool,
+    // The other versions which were written but not committed, whose records are not visible, set by `open_tree`.
+    uncommitted: Vec<u64>,
+    // Whether this store has marked its version as pending.
+    marked: bool,
+    // The read options of every get, `None` for the default options.
+    read_options: Option<&'a ReadOptions>,
+    // The write options of every put, can be a `WriteOptions` / `()` etc., `None` for the default options.
+    write_options: Option<&'a W>,
+}
+
+impl<'a, T, W> VersionedStore<'a, T, W> {
+    /// Create a store which writes the nodes of `version`, which must be after the last committed version.
+    pub fn new(db: &'a T, space: KeySpace<'a>, version: u64) -> Self {
+        VersionedStore {
+            inner: db,
+            space,
+            version,
+            read_only: false,
+            uncommitted: Vec::new(),
+            marked: false,
+            read_options: None,
+            write_options: None,
+        }
+    }
+
+    /// Create a read-only store of the committed `version`.
+    pub fn read_only(db: &'a T, space: KeySpace<'a>, version: u64) -> Self {
+        VersionedStore {
+            read_only: true,
+            ..Self::new(db, space, version)
+        }
+    }
+
+    /// Use `read_options` for every read of this store.
+    pub fn with_read_options(mut self, read_options: &'a ReadOptions) -> Self {
+        self.read_options = Some(read_options);
+        self
+    }
+
+    /// Use `write_options` for every write of this store.
+    pub fn with_write_options(mut self, write_options: &'a W) -> Self {
+        self.write_options = Some(write_options);
+        self
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn db(&self) -> &'a T {
+        self.inner
+    }
+
+    pub(crate) fn space(&self) -> KeySpace<'a> {
+        self.space
+    }
+
+    /// The root committed at `version`, `None` if the version was not committed or was pruned.
+    pub fn committed_root(&self, version: u64) -> Result<Option<H256>, Error>
+    where
+        T: GetCF<ReadOptions>,
+    {
+        self.space
+            .get(self.inner, &root_key(version), self.read_options)?
+            .map(|root| slice_to_root(&root))
+            .transpose()
+    }
+
+    /// The committed versions and their roots, oldest first.
+    pub fn versions(&self) -> Result<Vec<(u64, H256)>, Error>
+    where
+        T: IterateCF,
+    {
+        let prefix = self.space.key(&[ROOT_TAG]);
+        let versions = KeySpace::new(self.space.col, &prefix)
+            .scan(self.inner, self.read_options)?
+            .map(|(key, root)| {
+                Ok((
+                    slice_to_version(&key[prefix.len()..])?,
+                    slice_to_root(&root)?,
+                ))
+            })
+            .collect();
+        versions
+    }
+
+    // The last version committed up to `version` and its root.
+    fn last_committed(&self, version: u64) -> Result<Option<(u64, H256)>, Error>
+    where
+        T: IterateCF,
+    {
+        let prefix = self.space.key(&[ROOT_TAG]);
+        let last = KeySpace::new(self.space.col, &prefix)
+            .scan_from(
+                self.inner,
+                Some(&version.to_be_bytes()),
+                Direction::Reverse,
+                self.read_options,
+            )?
+            .next();
+        last.map(|(key, root)| {
+            Ok((
+                slice_to_version(&key[prefix.len()..])?,
+                slice_to_root(&root)?,
+            ))
+        })
+        .transpose()
+    }
+
+    /// The versions which were written but not committed, oldest first.
+    pub(crate) fn uncommitted_versions(&self) -> Result<Vec<u64>, Error>
+    where
+        T: IterateCF,
+    {
+        let prefix = self.space.key(&[PENDING_TAG]);
+        let mut versions = Vec::new();
+        for (key, _) in
+            KeySpace::new(self.space.col, &prefix).scan(self.inner, self.read_options)?
+        {
+            let version = slice_to_version(&key[prefix.len()..])?;
+            // the marker of a committed version is left if the commit was not written at once
+            if !matches!(self.last_committed(version)?, Some((committed, _)) if committed == version)
+            {
+                versions.push(version);
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Record `root` as the root of the version of this store.
+    pub fn commit(&self, root: &H256) -> Result<(), Error>
+    where
+        T: PutCF<W> + DeleteCF<W>,
+    {
+        self.check_writable()?;
+        self.space.put(
+            self.inner,
+            &root_key(self.version),
+            root.as_slice(),
+            self.write_options,
+        )?;
+        self.space
+            .delete(self.inner, &pending_key(self.version), self.write_options)
+    }
+
+    // Read the value of the record of `key` visible at the version of this store.
+    fn get_record(&self, tag: u8, key: &[u8]) -> Result<Option<Box<[u8]>>, Error>
+    where
+        T: IterateCF,
+    {
+        let prefix = self.space.key(&[&[tag], key].concat());
+        let records = KeySpace::new(self.space.col, &prefix)
+            .scan_from(
+                self.inner,
+                Some(&self.version.to_be_bytes()),
+                Direction::Reverse,
+                self.read_options,
+            )?
+            // skip the records of longer keys starting with this key
+            .filter(|(record_key, _)| record_key.len() == prefix.len() + 8);
+        let mut record = None;
+        for (record_key, value) in records {
+            if !self
+                .uncommitted
+                .contains(&slice_to_version(&record_key[prefix.len()..])?)
+            {
+                record = Some((record_key, value));
+                break;
+            }
+        }
+        match record {
+            Some((_, value)) => match value.first() {
+                Some(&PRESENT) => Ok(Some(value[1..].into())),
+                Some(&REMOVED) => Ok(None),
+                _ => Err(Error::Store(format!(
+                    "invalid versioned record of key {:?}: {:?}",
+                    key, value
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn put_record(&mut self, tag: u8, key: &[u8], value: Option<&[u8]>) -> Result<(), Error>
+    where
+        T: PutCF<W>,
+    {
+        self.check_writable()?;
+        if !self.marked {
+            self.space.put(
+                self.inner,
+                &pending_key(self.version),
+                &[],
+                self.write_options,
+            )?;
+            self.marked = true;
+        }
+        let value = match value {
+            Some(value) => [&[PRESENT], value].concat(),
+            None => vec![REMOVED],
+        };
+        self.space.put(
+            self.inner,
+            &record_key(tag, key, self.version),
+            &value,
+            self.write_options,
+        )
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::Store(format!(
+                "version {} of the store is read-only",
+                self.version
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn record_key(tag: u8, key: &[u8], version: u64) -> Vec<u8> {
+    [&[tag], key, &version.to_be_bytes()].concat()
+}
+
+pub(crate) fn root_key(version: u64) -> Vec<u8> {
+    [&[ROOT_TAG][..], &version.to_be_bytes()].concat()
+}
+
+pub(crate) fn pending_key(version: u64) -> Vec<u8> {
+    [&[PENDING_TAG][..], &version.to_be_bytes()].concat()
+}
+
+pub(crate) fn slice_to_version(slice: &[u8]) -> Result<u64, Error> {
+    let version: [u8; 8] = slice
+        .try_into()
+        .map_err(|_| Error::Store(format!("invalid version: {:?}", slice)))?;
+    Ok(u64::from_be_bytes(version))
+}
+
+fn slice_to_root(slice: &[u8]) -> Result<H256, Error> {
+    let root: [u8; 32] = slice
+        .try_into()
+        .map_err(|_| Error::Store(format!("invalid root record: {:?}", slice)))?;
+    Ok(root.into())
+}
+
+impl<'a, V, T, W> StoreReadOps<V> for VersionedStore<'a, T, W>
+where
+    V: From<Box<[u8]>>,
+    T: IterateCF,
+{
+    fn get_branch(&self, branch_key: &BranchKey) -> Result<Option<BranchNode>, Error> {
+        self.get_record(BRANCH_TAG, &branch_key_to_vec(branch_key))?
+            .map(|v| slice_to_branch_node(&v).map_err(|e| Error::Store(e.to_string())))
+            .transpose()
+    }
+
+    fn get_leaf(&self, leaf_key: &H256) -> Result<Option<V>, Error> {
+        Ok(self
+            .get_record(LEAF_TAG, leaf_key.as_slice())?
+            .map(|v| v.into()))
+    }
+}
+
+impl<'a, V, T, W> StoreWriteOps<V> for VersionedStore<'a, T, W>
+where
+    V: AsRef<[u8]>,
+    T: PutCF<W>,
+{
+    fn insert_branch(&mut self, node_key: BranchKey, branch: BranchNode) -> Result<(), Error> {
+        self.put_record(
+            BRANCH_TAG,
+            &branch_key_to_vec(&node_key),
+            Some(&branch_node_to_vec(&branch)),
+        )
+    }
+
+    fn insert_leaf(&mut self, leaf_key: H256, leaf: V) -> Result<(), Error> {
+        self.put_record(LEAF_TAG, leaf_key.as_slice(), Some(leaf.as_ref()))
+    }
+
+    fn remove_branch(&mut self, node_key: &BranchKey) -> Result<(), Error> {
+        self.put_record(BRANCH_TAG, &branch_key_to_vec(node_key), None)
+    }
+
+    fn remove_leaf(&mut self, leaf_key: &H256) -> Result<(), Error> {
+        self.put_record(LEAF_TAG, leaf_key.as_slice(), None)
+    }
+}
+
+/// Open the tree of a versioned store.
+///
+/// A read-only store opens the root committed at its version, and returns an error if the version was not
+/// committed or was pruned. A writable store opens the root of the last committed version, or an empty tree,
+/// and returns an error if its version is not after the last committed version or was written but not committed.
+/// The records of the versions which were written but not committed are not visible to the opened tree.
+pub fn open_tree<H, V, T, W>(
+    mut store: VersionedStore<'_, T, W>,
+) -> Result<SparseMerkleTree<H, V, VersionedStore<'_, T, W>>, Error>
+where
+    H: Hasher + Default,
+    V: Value,
+    T: IterateCF,
+{
+    let root = if store.read_only {
+        match store.last_committed(store.version)? {
+            Some((version, root)) if version == store.version => root,
+            _ => {
+                return Err(Error::Store(format!(
+                    "version {} was not committed or was pruned",
+                    store.version
+                )))
+            }
+        }
+    } else {
+        match store.last_committed(u64::MAX)? {
+            Some((version, _)) if version >= store.version => {
+                return Err(Error::Store(format!(
+                    "version {} is not after the last committed version {}",
+                    store.version, version
+                )))
+            }
+            Some((_, root)) => root,
+            None => H256::zero(),
+        }
+    };
+    store.uncommitted = store.uncommitted_versions()?;
+    if !store.read_only && store.uncommitted.contains(&store.version) {
+        return Err(Error::Store(format!(
+            "version {} was written but not committed",
+            store.version
+        )));
+    }
+    Ok(SparseMerkleTree::new(root, store))
+}